@@ -0,0 +1,154 @@
+use container::ColorModel;
+use jpeg::{self, Coefficients};
+use markers::Error;
+use {Plane, PACK_ALIGNMENT};
+
+// where a plane sits in the atlas, along with its sampling factors so the
+// conversion can find the sample for each pixel
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub h: u8,
+    pub v: u8,
+}
+
+impl Region {
+    // the part of the region an image of the given size covers, which leaves
+    // out the padding to whole MCUs. h_max and v_max are the largest sampling
+    // factors of any plane.
+    pub fn covered_dims(&self, width: u32, height: u32, h_max: u8, v_max: u8) -> (u32, u32) {
+        let (h_max, v_max) = (h_max as u32, v_max as u32);
+        let covered_width = (width * self.h as u32 + h_max - 1) / h_max;
+        let covered_height = (height * self.v as u32 + v_max - 1) / v_max;
+        (covered_width.min(self.width), covered_height.min(self.height))
+    }
+}
+
+// every plane of an image combined so that they can be decoded into a single
+// texture in one set of passes. the planes are stacked top to bottom in
+// component order, so the atlas is as wide as the widest plane and as tall as
+// all of them together.
+pub struct Atlas {
+    pub width: u32,
+    pub height: u32,
+    pub regions: Vec<Region>,
+    // the packed coefficients of every plane, one after another
+    pub packed_coeffs: Vec<u16>,
+    // the start of each block in packed_coeffs, covering the whole atlas
+    pub packed_indices: Vec<u32>,
+    // the quantization tables in component order, 64 entries each
+    pub quant_tables: Vec<u16>,
+    pub color_model: ColorModel,
+    // bits per sample, which is 8 unless the image says otherwise
+    pub precision: u8,
+}
+
+impl Atlas {
+    // guesses the color model from the number of planes, taking four to be
+    // uninverted CMYK
+    pub fn new(planes: &[Plane]) -> Atlas {
+        let color_model = match planes.len() {
+            1 => ColorModel::Gray,
+            4 => ColorModel::Cmyk,
+            _ => ColorModel::YCbCr,
+        };
+        Atlas::with_color_model(planes, color_model)
+    }
+
+    pub fn with_color_model(planes: &[Plane], color_model: ColorModel) -> Atlas {
+        assert!(planes.len() == color_model.num_planes(), "wrong number of planes for {:?}",
+                color_model);
+        let width = planes.iter().map(|p| p.width).max().unwrap();
+        let height = planes.iter().map(|p| p.height).sum();
+        let blocks_x = (width >> 3) as usize;
+
+        let mut regions = Vec::with_capacity(planes.len());
+        let mut packed_coeffs = vec![];
+        let mut packed_indices = vec![0; blocks_x * (height >> 3) as usize];
+        let mut quant_tables = Vec::with_capacity(64 * planes.len());
+        let mut y = 0;
+        for plane in planes {
+            // each plane's coefficients are already padded to PACK_ALIGNMENT,
+            // so they stay aligned after being appended
+            let base = packed_coeffs.len() as u32;
+            packed_coeffs.extend_from_slice(&plane.packed_coeffs);
+            let plane_blocks_x = (plane.width >> 3) as usize;
+            for (b, &index) in plane.packed_indices.iter().enumerate() {
+                let row = (y >> 3) as usize + b / plane_blocks_x;
+                packed_indices[row * blocks_x + b % plane_blocks_x] = base + index;
+            }
+            quant_tables.extend_from_slice(&plane.quant_table);
+            regions.push(Region {
+                x: 0,
+                y: y,
+                width: plane.width,
+                height: plane.height,
+                h: plane.h,
+                v: plane.v,
+            });
+            y += plane.height;
+        }
+
+        // blocks to the right of narrower planes aren't part of any plane, so
+        // point them at an end of block marker of their own
+        if planes.iter().any(|p| p.width < width) {
+            let empty = packed_coeffs.len() as u32;
+            packed_coeffs.extend(vec![0; PACK_ALIGNMENT]);
+            let mut y = 0;
+            for plane in planes {
+                for row in (y >> 3)..((y + plane.height) >> 3) {
+                    let start = row as usize * blocks_x + (plane.width >> 3) as usize;
+                    let end = (row as usize + 1) * blocks_x;
+                    for index in &mut packed_indices[start..end] {
+                        *index = empty;
+                    }
+                }
+                y += plane.height;
+            }
+        }
+
+        Atlas {
+            width: width,
+            height: height,
+            regions: regions,
+            packed_coeffs: packed_coeffs,
+            packed_indices: packed_indices,
+            quant_tables: quant_tables,
+            color_model: color_model,
+            precision: 8,
+        }
+    }
+
+    // packs every plane of a decoded JPEG, whose components say what the
+    // color model is
+    pub fn from_coefficients(coeffs: &Coefficients) -> Result<Atlas, Error> {
+        let color_model = try!(coeffs.color_model());
+        let planes: Vec<_> = coeffs.planes.iter()
+            .map(|p| Plane::new(p.width, p.height, p.h, p.v, &p.data, &p.quant_table))
+            .collect();
+        let mut atlas = Atlas::with_color_model(&planes, color_model);
+        atlas.precision = coeffs.precision;
+        Ok(atlas)
+    }
+
+    // the first block row of each region, which the shaders use to pick the
+    // quantization table. unused entries are past the end of the atlas.
+    pub fn region_rows(&self) -> [i32; 4] {
+        assert!(self.regions.len() <= 4, "at most four planes fit in an atlas");
+        let mut rows = [i32::max_value(); 4];
+        for (row, region) in rows.iter_mut().zip(self.regions.iter()) {
+            *row = (region.y >> 3) as i32;
+        }
+        rows
+    }
+}
+
+// decodes a JPEG file into an atlas, returning the image dimensions with it
+pub fn load_jpeg(path: &str) -> Result<(u32, u32, Atlas), Error> {
+    let coeffs = try!(jpeg::decode_file(path));
+    let atlas = try!(Atlas::from_coefficients(&coeffs));
+    Ok((coeffs.width, coeffs.height, atlas))
+}
@@ -2,125 +2,86 @@ extern crate clap;
 extern crate gpeg;
 
 use clap::{Arg, App};
-use gpeg::make_zigzag_table;
+use gpeg::markers::{self, Error, SegmentType};
 use std::fs::File;
 use std::io::{self, BufReader, Read};
 
-#[derive(Debug, PartialEq, Eq)]
-enum FrameType {
-    BaselineDct,
-}
-
-#[derive(Debug, PartialEq, Eq)]
-enum SegmentType {
-    StartOfImage,
-    App(u8),
-    DefineQuantizationTable,
-    StartOfFrame(FrameType),
-    DefineHuffmanTable,
-    StartOfScan,
-    EndOfImage
-}
+fn dump(input: &mut Iterator<Item=io::Result<u8>>) -> Result<(), Error> {
+    let mut head = try!(markers::read_header(input));
+    while head.0 != SegmentType::EndOfImage {
+        println!("segment {:?}", head);
+        match head.0 {
+            SegmentType::StartOfScan => {
+                let scan = try!(markers::read_scan_header(input, head.1));
+                for c in scan.components.iter() {
+                    println!("c={} td={} ta={}", c.id, c.td, c.ta);
+                }
+                println!("ss={} se={} ah={} al={}", scan.ss, scan.se, scan.ah, scan.al);
+                println!("skipping to next marker");
+                head = try!(markers::find_marker(input));
+            },
+            SegmentType::DefineQuantizationTable => {
+                for table in try!(markers::read_quantization_table(input, head.1)) {
+                    println!("QUANT TABLE {}", table.id);
+                    for j in 0..8 {
+                        for i in 0..8 {
+                            print!("{:4} ", table.values[j * 8 + i]);
+                        }
+                        println!("");
+                    }
+                }
 
-fn skip(input: &mut Iterator<Item=io::Result<u8>>, bytes: usize) {
-    for _ in 0..bytes {
-        match input.next() {
-            Some(_) => (),
-            None => panic!("ran out of data trying to skip"),
-        }
-    }
-}
+                head = try!(markers::read_header(input));
+            },
+            SegmentType::StartOfFrame(frame_type) => {
+                let frame = try!(markers::read_frame_header(input, frame_type, head.1));
+                println!("p={} y={} x={} nf={}", frame.precision, frame.height, frame.width,
+                         frame.components.len());
+                for (i, c) in frame.components.iter().enumerate() {
+                    println!("i={} c={} h={} v={} tq={}", i, c.id, c.h, c.v, c.tq);
+                }
 
-fn read_word(input: &mut Iterator<Item=io::Result<u8>>) -> u16 {
-    let hi = input.next().unwrap().unwrap();
-    let lo = input.next().unwrap().unwrap();
-    ((hi as u16) << 8) | (lo as u16)
-}
+                head = try!(markers::read_header(input));
+            },
+            SegmentType::DefineHuffmanTable => {
+                for table in try!(markers::read_huffman_table(input, head.1)) {
+                    println!("tc={} th={} l={:?}", table.class, table.id, table.counts);
+                }
 
-fn parse_header(input: &mut Iterator<Item=io::Result<u8>>, hi: u8, lo: u8) -> (SegmentType, usize) {
-    assert!(hi == 0xff);
-    let ty = match lo {
-        0xd8 => SegmentType::StartOfImage,
-        code @ 0xe0...0xef => SegmentType::App(code & 0xf),
-        0xdb => SegmentType::DefineQuantizationTable,
-        0xc0 => SegmentType::StartOfFrame(FrameType::BaselineDct),
-        0xc4 => SegmentType::DefineHuffmanTable,
-        0xda => SegmentType::StartOfScan,
-        0xd9 => SegmentType::EndOfImage,
-        code => panic!("unknown segment {:x}", code),
-    };
-    let len = match ty {
-        SegmentType::StartOfImage => 0,
-        SegmentType::EndOfImage => 0,
-        _ => read_word(input) - 2,
-    };
-    (ty, len as usize)
-}
+                head = try!(markers::read_header(input));
+            },
+            SegmentType::DefineArithmeticConditioning => {
+                for table in try!(markers::read_arithmetic_conditioning(input, head.1)) {
+                    println!("tc={} tb={} cs={}", table.class, table.id, table.value);
+                }
 
-fn read_header(input: &mut Iterator<Item=io::Result<u8>>) -> (SegmentType, usize) {
-    let hi = input.next().unwrap().unwrap();
-    let lo = input.next().unwrap().unwrap();
-    parse_header(input, hi, lo)
-}
+                head = try!(markers::read_header(input));
+            },
+            SegmentType::DefineRestartInterval => {
+                let ri = try!(markers::read_restart_interval(input, head.1));
+                println!("restart interval {} MCUs", ri);
 
-fn find_marker(input: &mut Iterator<Item=io::Result<u8>>) -> (SegmentType, usize) {
-    loop {
-        let hi = input.next().unwrap().unwrap();
-        if hi == 0xff {
-            let lo = input.next().unwrap().unwrap();
-            if lo != 0x00 {
-                return parse_header(input, hi, lo);
+                head = try!(markers::read_header(input));
+            },
+            SegmentType::App(n) => {
+                let app = try!(markers::read_app_segment(input, n, head.1));
+                let id: String = app.data.iter()
+                    .take_while(|&&b| b != 0)
+                    .map(|&b| b as char)
+                    .collect();
+                println!("APP{} {:?} ({} bytes)", app.n, id, app.data.len());
+
+                head = try!(markers::read_header(input));
+            },
+            _ => {
+                println!("skipping {} bytes", head.1);
+                try!(markers::skip(input, head.1));
+                head = try!(markers::read_header(input));
             }
         }
     }
-}
-
-fn read_quantization_table(input: &mut Iterator<Item=io::Result<u8>>) -> (usize, Vec<u16>) {
-    let pqtq = input.next().unwrap().unwrap();
-    // only support baseline which has pq=0 always
-    assert!((pqtq & 0xf0) == 0);
-    let tq = (pqtq & 0x0f) as usize;
-    let zigzag = make_zigzag_table(8);
-    let mut table = vec![0; 64];
-    for j in 0..8 {
-        for i in 0..8 {
-            table[zigzag[j][i]] = input.next().unwrap().unwrap() as u16;
-        }
-    }
-    (tq, table)
-}
-
-fn read_frame_header(input: &mut Iterator<Item=io::Result<u8>>, frame_type: FrameType) {
-    let p = input.next().unwrap().unwrap();
-    let y = read_word(input);
-    let x = read_word(input);
-    let nf = input.next().unwrap().unwrap();
-
-    println!("p={} y={} x={} nf={}", p, y, x, nf);
-    for i in 0..nf {
-        let c = input.next().unwrap().unwrap();
-        let hv = input.next().unwrap().unwrap();
-        let h = (hv & 0xf0) >> 4;
-        let v = hv & 0x0f;
-        let tq = input.next().unwrap().unwrap();
-        println!("i={} c={} h={} v={} tq={}", i, c, h, v, tq);
-    }
-}
-
-fn read_huffman_table(input: &mut Iterator<Item=io::Result<u8>>) {
-    let tcth = input.next().unwrap().unwrap();
-    let tc = (tcth & 0xf0) >> 4;
-    let th = tcth & 0x0f;
-    let mut l: Vec<u8> = vec![0; 16];
-    for _ in 0..16 {
-        l.push(input.next().unwrap().unwrap());
-    }
-    for v in l.iter() {
-        for _ in 0..*v {
-            input.next().unwrap().unwrap();
-        }
-    }
-    println!("tc={} th={} l={:?}", tc, th, l);
+    println!("segment {:?}", head);
+    Ok(())
 }
 
 fn main() {
@@ -140,42 +101,8 @@ fn main() {
     let r = BufReader::new(f);
     let mut input = r.bytes();
 
-    let mut head = read_header(&mut input);
-    while head.0 != SegmentType::EndOfImage {
-        println!("segment {:?}", head);
-        match head.0 {
-            SegmentType::StartOfScan => {
-                println!("skipping to next marker");
-                head = find_marker(&mut input);
-            },
-            SegmentType::DefineQuantizationTable => {
-                let (tq, table) = read_quantization_table(&mut input);
-                println!("QUANT TABLE {}", tq);
-                for j in 0..8 {
-                    for i in 0..8 {
-                        print!("{:4} ", table[j * 8 + i]);
-                    }
-                    println!("");
-                }
-                
-                head = read_header(&mut input);
-            },
-            SegmentType::StartOfFrame(frame_type) => {
-                read_frame_header(&mut input, frame_type);
-
-                head = read_header(&mut input);
-            },
-            SegmentType::DefineHuffmanTable => {
-                read_huffman_table(&mut input);
-
-                head = read_header(&mut input);
-            },
-            _ => {
-                println!("skipping {} bytes", head.1);
-                skip(&mut input, head.1);
-                head = read_header(&mut input);
-            }
-        }
+    if let Err(err) = dump(&mut input) {
+        println!("error: {}", err);
+        std::process::exit(1);
     }
-    println!("segment {:?}", head);
 }
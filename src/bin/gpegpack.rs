@@ -0,0 +1,124 @@
+extern crate clap;
+extern crate gpeg;
+
+use clap::{Arg, App};
+use gpeg::container::{self, ColorModel, Image};
+use gpeg::jpeg::CoeffPlane;
+use gpeg::{jpeg, parse_dims, planefile, Plane};
+
+// plane files don't say what their planes are, so guess from how many there
+// are
+fn guess_color_model(coeffs: &[CoeffPlane]) -> Result<ColorModel, String> {
+    match coeffs.len() {
+        1 => Ok(ColorModel::Gray),
+        3 => Ok(ColorModel::YCbCr),
+        4 => Ok(ColorModel::Cmyk),
+        n => Err(format!("{} components are not supported", n)),
+    }
+}
+
+// plane files are padded out to the MCU grid, as the decoder lays planes out,
+// so each plane must be exactly that size for the image to be rebuilt
+fn check_plane_sizes(width: u32, height: u32, coeffs: &[CoeffPlane]) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err("size must not be zero".to_owned());
+    }
+    let h_max = coeffs.iter().map(|p| p.h as u32).max().unwrap();
+    let v_max = coeffs.iter().map(|p| p.v as u32).max().unwrap();
+    let mcus_x = (width + 8 * h_max - 1) / (8 * h_max);
+    let mcus_y = (height + 8 * v_max - 1) / (8 * v_max);
+    for (i, p) in coeffs.iter().enumerate() {
+        let (plane_width, plane_height) = (mcus_x * p.h as u32 * 8, mcus_y * p.v as u32 * 8);
+        if (p.width, p.height) != (plane_width, plane_height) {
+            return Err(format!("plane {} is {}x{} but a {}x{} image with {}x{} sampling \
+                                needs {}x{}", i, p.width, p.height, width, height, p.h, p.v,
+                               plane_width, plane_height));
+        }
+    }
+    Ok(())
+}
+
+fn pack_image(width: u32, height: u32, color_model: ColorModel, precision: u8,
+              coeffs: &[CoeffPlane]) -> Image {
+    Image {
+        width: width,
+        height: height,
+        color_model: color_model,
+        precision: precision,
+        planes: coeffs.iter()
+            .map(|p| Plane::new(p.width, p.height, p.h, p.v, &p.data, &p.quant_table))
+            .collect(),
+    }
+}
+
+fn main() {
+    let matches = App::new("gpegpack")
+        .about("Packs a JPEG or plane file into a .gpeg container ready for GPU upload")
+        .arg(Arg::with_name("INPUT")
+             .help("JPEG file, or a plane file if --size is given")
+             .required(true)
+             .index(1))
+        .arg(Arg::with_name("output")
+             .short("o")
+             .long("output")
+             .value_name("FILE")
+             .help(".gpeg file to write")
+             .takes_value(true)
+             .required(true))
+        .arg(Arg::with_name("size")
+             .long("size")
+             .value_name("WxH")
+             .help("Image dimensions when packing a plane file")
+             .takes_value(true))
+        .arg(Arg::with_name("precision")
+             .long("precision")
+             .value_name("BITS")
+             .help("Bits per sample when packing a plane file, 8 or 12 (default 8)")
+             .takes_value(true))
+        .get_matches();
+
+    let input = matches.value_of("INPUT").unwrap();
+    let output = matches.value_of("output").unwrap();
+    let precision = match matches.value_of("precision").unwrap_or("8") {
+        "8" => 8,
+        "12" => 12,
+        _ => {
+            println!("error: precision must be 8 or 12");
+            std::process::exit(1);
+        },
+    };
+
+    let image = match matches.value_of("size") {
+        Some(size) => {
+            match parse_dims(size) {
+                Some((width, height)) => {
+                    planefile::read_file(input)
+                        .map_err(|e| format!("{}: {}", input, e))
+                        .and_then(|planes| {
+                            let color_model = try!(guess_color_model(&planes));
+                            try!(check_plane_sizes(width, height, &planes));
+                            Ok(pack_image(width, height, color_model, precision, &planes))
+                        })
+                },
+                None => Err("size must be given as WxH".to_owned()),
+            }
+        },
+        None => {
+            jpeg::decode_file(input)
+                .and_then(|coeffs| {
+                    let color_model = try!(coeffs.color_model());
+                    Ok(pack_image(coeffs.width, coeffs.height, color_model, coeffs.precision,
+                                  &coeffs.planes))
+                })
+                .map_err(|e| format!("{}: {}", input, e))
+        },
+    };
+
+    let result = image.and_then(|image| {
+        container::write_file(output, &image).map_err(|e| format!("{}: {}", output, e))
+    });
+    if let Err(err) = result {
+        println!("error: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -1,33 +1,28 @@
 extern crate gpeg;
 
-use gpeg::{make_zigzag_table, pack_coeffs, read_data, Plane};
+use gpeg::{pack_coeffs, read_data};
 
 fn main() {
     let width = 1024;
     let height = 576;
     let planes = vec![
-        Plane {
-            width: width,
-            height: height,
-            data: read_data("f1.Y"),
-        },
-        Plane {
-            width: width / 2,
-            height: height / 2,
-            data: read_data("f1.Cb"),
-        },
-        Plane {
-            width: width / 2,
-            height: height / 2,
-            data: read_data("f1.Cr"),
-        },
+        (width, height, "f1.Y"),
+        (width / 2, height / 2, "f1.Cb"),
+        (width / 2, height / 2, "f1.Cr"),
     ];
 
     let mut packed_coeffs = 0;
-    for (plane_i, plane) in planes.iter().enumerate() {
-        let (packed, _) = pack_coeffs(plane.width, plane.height, &plane.data);
+    for (plane_i, &(w, h, f)) in planes.iter().enumerate() {
+        let data = match read_data(f) {
+            Ok(data) => data,
+            Err(err) => {
+                println!("error: {}: {}", f, err);
+                std::process::exit(1);
+            },
+        };
+        let (packed, _) = pack_coeffs(w, h, &data);
         packed_coeffs += packed.len();
-        let num_blocks = (plane.width >> 3) * (plane.height >> 3);
+        let num_blocks = (w >> 3) * (h >> 3);
         let unpacked_size = num_blocks * 64 * 2;
 
         println!("plane {}: total blocks: {}", plane_i, num_blocks);
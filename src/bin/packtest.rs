@@ -1,43 +1,6 @@
 extern crate gpeg;
 
-use gpeg::{make_zigzag_table, pack_coeffs, read_data};
-
-fn unpack(p: u16) -> (u16, i16) {
-    let zeros: u16 = p >> 12;
-    let coeff: i16 = if (p & 0x800) == 0x800 {
-        ((p & 0xfff) | 0xf000) as i16
-    } else {
-        (p & 0xfff) as i16
-    };
-    (zeros, coeff)
-}
-
-fn unpack_coeffs(width: u32, height: u32, data: &[u16]) -> Vec<i16> {
-    let mut coeffs: Vec<i16> = vec![0; (width * height) as usize];
-    let zigzag = make_zigzag_table(width);
-    let block_width = width >> 3;
-    let block_height = height >> 3;
-    let mut data_idx = 0;
-    for by in 0..block_height {
-        for bx in 0..block_width {
-            let block_offset = (by << 3) * width + (bx << 3);
-            let mut zz_idx: usize = 0;
-            while zz_idx < 64 {
-                let (zeros, coeff) = unpack(data[data_idx]);
-                // println!("read ({},{}) at index {} of block ({},{})",
-                //          zeros, coeff, zz_idx, bx, by);
-                data_idx += 1;
-                if zeros == 0 && coeff == 0 {
-                    break;
-                }
-                zz_idx += zeros as usize;
-                coeffs[block_offset as usize + zigzag[zz_idx >> 3][zz_idx % 8]] = coeff;
-                zz_idx += 1;
-            }
-        }
-    }
-    coeffs
-}
+use gpeg::{pack_coeffs, read_data, unpack_coeffs};
 
 fn print_block(data: &[i16], bx: u32, by: u32, stride: u32) {
     let block_offset = ((by << 3) * stride + (bx << 3)) as usize;
@@ -58,9 +21,15 @@ fn main() {
         (width >> 1, height >> 1, "f1.Cr"),
         ];
     for (w, h, f) in planes {
-        let data = read_data(f);
+        let data = match read_data(f) {
+            Ok(data) => data,
+            Err(err) => {
+                println!("error: {}: {}", f, err);
+                std::process::exit(1);
+            },
+        };
         let (packed, indices) = pack_coeffs(w, h, &data);
-        let unpacked = unpack_coeffs(w, h, &packed);
+        let unpacked = unpack_coeffs(w, h, &packed, &indices);
         assert!(data.len() == unpacked.len());
 
         if w == width {
@@ -0,0 +1,91 @@
+extern crate clap;
+extern crate gpeg;
+
+use clap::{Arg, App};
+use gpeg::jpeg::CoeffPlane;
+use gpeg::{parse_dims, parse_sampling, planefile, read_data};
+
+fn convert(files: &[&str], width: u32, height: u32, sampling: &[(u32, u32)], output: &str)
+           -> Result<(), String> {
+    if files.len() != sampling.len() {
+        return Err(format!("expected sampling factors for each of the {} planes", files.len()));
+    }
+    let h_max = sampling.iter().map(|&(h, _)| h).max().unwrap();
+    let v_max = sampling.iter().map(|&(_, v)| v).max().unwrap();
+    // the text dumps are padded out to the MCU grid
+    let mcus_x = (width + 8 * h_max - 1) / (8 * h_max);
+    let mcus_y = (height + 8 * v_max - 1) / (8 * v_max);
+    let mut planes = vec![];
+    for (i, (f, &(h, v))) in files.iter().zip(sampling.iter()).enumerate() {
+        let plane_width = mcus_x * h * 8;
+        let plane_height = mcus_y * v * 8;
+        let data = try!(read_data(f).map_err(|e| format!("{}: {}", f, e)));
+        if data.len() != (plane_width * plane_height) as usize {
+            return Err(format!("{}: expected {}x{} coefficients, got {}",
+                               f, plane_width, plane_height, data.len()));
+        }
+        planes.push(CoeffPlane {
+            id: i as u8 + 1,
+            h: h as u8,
+            v: v as u8,
+            width: plane_width,
+            height: plane_height,
+            data: data,
+            // the text dumps are already dequantized
+            quant_table: vec![1; 64],
+        });
+    }
+    planefile::write_file(output, &planes).map_err(|e| format!("{}: {}", output, e))
+}
+
+fn main() {
+    let matches = App::new("txt2plane")
+        .about("Converts text coefficient dumps into a binary plane file")
+        .arg(Arg::with_name("INPUT")
+             .help("Text coefficient dumps, in component order")
+             .required(true)
+             .multiple(true)
+             .index(1))
+        .arg(Arg::with_name("output")
+             .short("o")
+             .long("output")
+             .value_name("FILE")
+             .help("Plane file to write")
+             .takes_value(true)
+             .required(true))
+        .arg(Arg::with_name("size")
+             .long("size")
+             .value_name("WxH")
+             .help("Image dimensions of the coefficient dumps")
+             .takes_value(true)
+             .required(true))
+        .arg(Arg::with_name("sampling")
+             .long("sampling")
+             .value_name("HxV")
+             .help("Sampling factors of each coefficient dump (default 2x2 1x1 1x1)")
+             .takes_value(true)
+             .multiple(true))
+        .get_matches();
+
+    let files: Vec<&str> = matches.values_of("INPUT").unwrap().collect();
+    let output = matches.value_of("output").unwrap();
+    let (width, height) = match parse_dims(matches.value_of("size").unwrap()) {
+        Some(dims) => dims,
+        None => {
+            println!("error: size must be given as WxH");
+            std::process::exit(1);
+        },
+    };
+    let sampling = match parse_sampling(matches.values_of("sampling"), files.len()) {
+        Ok(sampling) => sampling,
+        Err(err) => {
+            println!("error: {}", err);
+            std::process::exit(1);
+        },
+    };
+
+    if let Err(err) = convert(&files, width, height, &sampling, output) {
+        println!("error: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,116 @@
+extern crate clap;
+extern crate glium;
+extern crate gpeg;
+
+use clap::{Arg, App};
+use glium::DisplayBuild;
+use glium::backend::Facade;
+use gpeg::atlas::{self, Atlas};
+use gpeg::decoder::{Backend, ConvertMode, Decoder, UnpackMode};
+use gpeg::{read_data, Plane};
+use std::time::Instant;
+
+// the sample frame is 1024x576 and 4:2:0
+fn load_sample() -> Result<(u32, u32, Atlas), String> {
+    let (width, height) = (1024, 576);
+    let (mcus_x, mcus_y) = (width / 16, height / 16);
+    let planes = vec![(2, 2, "f1.Y"),
+                      (1, 1, "f1.Cb"),
+                      (1, 1, "f1.Cr")];
+    let mut packed = vec![];
+    for &(h, v, f) in planes.iter() {
+        let plane_width = mcus_x * h as u32 * 8;
+        let plane_height = mcus_y * v as u32 * 8;
+        let data = try!(read_data(f).map_err(|e| format!("{}: {}", f, e)));
+        if data.len() != (plane_width * plane_height) as usize {
+            return Err(format!("{}: expected {}x{} coefficients, got {}",
+                               f, plane_width, plane_height, data.len()));
+        }
+        packed.push(Plane::new(plane_width, plane_height, h, v, &data, &[1; 64]));
+    }
+    Ok((width, height, Atlas::new(&packed)))
+}
+
+fn main() {
+    let matches = App::new("unpackbench")
+        .about("Compares the unpack modes, the compute backend and blended color conversion")
+        .arg(Arg::with_name("INPUT")
+             .help("JPEG file to decode (default is the f1.* sample frame)")
+             .index(1))
+        .arg(Arg::with_name("iterations")
+             .short("n")
+             .long("iterations")
+             .value_name("N")
+             .help("Number of decodes to time for each mode (default 100)")
+             .takes_value(true))
+        .get_matches();
+
+    let iterations: u32 = match matches.value_of("iterations").unwrap_or("100").parse() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            println!("error: iterations must be a positive number");
+            std::process::exit(1);
+        },
+    };
+    let image = match matches.value_of("INPUT") {
+        Some(path) => atlas::load_jpeg(path).map_err(|e| format!("{}: {}", path, e)),
+        None => load_sample(),
+    };
+    let (width, height, atlas) = match image {
+        Ok(image) => image,
+        Err(err) => {
+            println!("error: {}", err);
+            std::process::exit(1);
+        },
+    };
+
+    let display = glium::glutin::WindowBuilder::new()
+        .with_visibility(false)
+        .build_glium().unwrap();
+
+    // the unpack modes and backends must agree exactly, but blending sums the
+    // channels in a different order than the conversion shader, so it may be
+    // off by one
+    let mut decoders = vec![];
+    for &convert_mode in [ConvertMode::Shader, ConvertMode::Blend].iter() {
+        for &mode in [UnpackMode::Pixel, UnpackMode::Row].iter() {
+            let mut decoder = Decoder::new(&display, atlas.width, atlas.height);
+            decoder.set_unpack_mode(mode);
+            decoder.set_convert_mode(convert_mode);
+            let tolerance = if convert_mode == ConvertMode::Blend { 1 } else { 0 };
+            decoders.push((format!("{:?}, {:?}", mode, convert_mode), tolerance, decoder));
+        }
+    }
+    match Decoder::with_backend(&display, atlas.width, atlas.height, Backend::Compute) {
+        Some(decoder) => decoders.push(("Compute".to_owned(), 0, decoder)),
+        None => println!("compute shaders aren't supported, skipping them"),
+    }
+
+    let mut reference: Option<Vec<u8>> = None;
+    for &(ref name, tolerance, ref decoder) in decoders.iter() {
+        // the first decode warms things up and checks the modes agree
+        let pixels = decoder.decode_to_rgb(width, height, &atlas);
+        if let Some(ref expected) = reference {
+            let diff = expected.iter().zip(pixels.iter())
+                .map(|(&a, &b)| (a as i32 - b as i32).abs())
+                .max().unwrap_or(0);
+            if diff > tolerance {
+                println!("error: {} output differs from {} by up to {}",
+                         name, decoders[0].0, diff);
+                std::process::exit(1);
+            }
+        }
+        if reference.is_none() {
+            reference = Some(pixels);
+        }
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            decoder.decode(width, height, &atlas);
+        }
+        display.get_context().finish();
+        let elapsed = start.elapsed();
+        let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        println!("{}: {:.3} ms per decode", name, secs * 1000.0 / iterations as f64);
+    }
+}
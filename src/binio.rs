@@ -0,0 +1,49 @@
+// little endian helpers shared by the binary file formats
+
+use markers::Error;
+use std::io::{self, Read};
+
+pub fn read_u16(buf: &[u8]) -> u16 {
+    (buf[0] as u16) | ((buf[1] as u16) << 8)
+}
+
+pub fn read_u32(buf: &[u8]) -> u32 {
+    (buf[0] as u32) | ((buf[1] as u32) << 8) | ((buf[2] as u32) << 16) | ((buf[3] as u32) << 24)
+}
+
+pub fn write_u16(buf: &mut [u8], value: u16) {
+    buf[0] = value as u8;
+    buf[1] = (value >> 8) as u8;
+}
+
+pub fn write_u32(buf: &mut [u8], value: u32) {
+    buf[0] = value as u8;
+    buf[1] = (value >> 8) as u8;
+    buf[2] = (value >> 16) as u8;
+    buf[3] = (value >> 24) as u8;
+}
+
+// fills buf completely, returning false if the input ended before any of it
+// was read and an error if it ended part way through
+pub fn read_full(input: &mut Read, buf: &mut [u8]) -> Result<bool, Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(Error::Malformed("file is truncated")),
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(Error::Io(e)),
+        }
+    }
+    Ok(true)
+}
+
+// like read_full but the input must not end before buf is filled
+pub fn read_exact(input: &mut Read, buf: &mut [u8]) -> Result<(), Error> {
+    if buf.is_empty() || try!(read_full(input, buf)) {
+        Ok(())
+    } else {
+        Err(Error::Malformed("file is truncated"))
+    }
+}
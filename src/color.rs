@@ -0,0 +1,261 @@
+use atlas::{Atlas, Region};
+use container::ColorModel;
+
+// the primaries the YCbCr samples were encoded with
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColorSpace {
+    // what JFIF uses, and the default
+    Bt601,
+    // HD video
+    Bt709,
+}
+
+// the range of levels the YCbCr samples use
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColorRange {
+    // every level from 0 to 255, as JFIF does
+    Full,
+    // luma from 16 to 235 and chroma from 16 to 240, as video usually does
+    Limited,
+}
+
+// how planes with less resolution than the image are filled in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Upsampling {
+    // every pixel takes the sample it falls in, which is blocky but exact
+    Nearest,
+    // interpolates between the four nearest samples, which are sited at the
+    // center of the pixels they cover as JFIF has them
+    Bilinear,
+    // libjpeg's triangle filter, weighting the nearer sample 3 to 1 with
+    // integer math. it only applies where a plane has half the resolution of
+    // the image, and is nearest otherwise.
+    Fancy,
+}
+
+// converts decoded samples, which have no level shift applied, to RGB levels
+// from 0 to 255, or 4095 for 12-bit images. the conversion shaders are given
+// the same matrix, so the GPU and CPU conversions agree.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ColorMatrix {
+    // how much each of Y, Cb and Cr adds to R, G and B. these are columns as
+    // GLSL expects, so each one is also a plane's weights when blending.
+    pub columns: [[f32; 3]; 3],
+    // added to R, G and B, which takes care of the luma level shift and black
+    // level
+    pub offset: [f32; 3],
+}
+
+// the largest level a sample of the given precision can have
+pub fn max_level(precision: u8) -> f32 {
+    ((1 << precision) - 1) as f32
+}
+
+impl ColorMatrix {
+    // the matrix for 8-bit samples
+    pub fn new(space: ColorSpace, range: ColorRange) -> ColorMatrix {
+        ColorMatrix::with_precision(space, range, 8)
+    }
+
+    // higher precisions scale the levels up, so only the offset changes
+    pub fn with_precision(space: ColorSpace, range: ColorRange, precision: u8) -> ColorMatrix {
+        let (kr, kb) = match space {
+            ColorSpace::Bt601 => (0.299, 0.114),
+            ColorSpace::Bt709 => (0.2126, 0.0722),
+        };
+        let kg = 1.0 - kr - kb;
+        let (y_scale, c_scale, black) = match range {
+            ColorRange::Full => (1.0, 1.0, 0.0),
+            ColorRange::Limited => (255.0 / 219.0, 255.0 / 224.0, 16.0),
+        };
+        let cr_r = 2.0 * (1.0 - kr) * c_scale;
+        let cb_g = -2.0 * kb * (1.0 - kb) / kg * c_scale;
+        let cr_g = -2.0 * kr * (1.0 - kr) / kg * c_scale;
+        let cb_b = 2.0 * (1.0 - kb) * c_scale;
+        let scale = (1 << (precision - 8)) as f64;
+        let y_offset = ((128.0 - black) * scale * y_scale) as f32;
+        ColorMatrix {
+            columns: [[y_scale as f32; 3],
+                      [0.0, cb_g as f32, cb_b as f32],
+                      [cr_r as f32, cr_g as f32, 0.0]],
+            offset: [y_offset; 3],
+        }
+    }
+
+    // converts one pixel, rounding and saturating as writing to an 8-bit
+    // texture does. gray images have no chroma, so pass zero for it.
+    pub fn apply(&self, y: f32, cb: f32, cr: f32) -> (u8, u8, u8) {
+        let rgb = self.transform(y, cb, cr);
+        (to_u8(rgb[0]), to_u8(rgb[1]), to_u8(rgb[2]))
+    }
+
+    // the RGB levels before rounding and saturating
+    fn transform(&self, y: f32, cb: f32, cr: f32) -> [f32; 3] {
+        let c = &self.columns;
+        let channel = |i: usize| c[0][i] * y + c[1][i] * cb + c[2][i] * cr + self.offset[i];
+        [channel(0), channel(1), channel(2)]
+    }
+
+    // converts one pixel of a four plane image as the CMYK conversion shader
+    // does. YCCK uses the matrix to get back to the C, M and Y planes.
+    pub fn apply_cmyk(&self, color_model: ColorModel, samples: [f32; 4]) -> (u8, u8, u8) {
+        let rgb = self.cmyk_levels(color_model, samples, 8);
+        (to_u8(rgb[0]), to_u8(rgb[1]), to_u8(rgb[2]))
+    }
+
+    // converts one pixel of any color model to levels of the given precision,
+    // rounding and saturating as the conversion shaders do. the matrix must
+    // have been made for the same precision.
+    pub fn apply_u16(&self, color_model: ColorModel, samples: [f32; 4], precision: u8)
+                     -> (u16, u16, u16) {
+        let rgb = match color_model {
+            ColorModel::Gray => self.transform(samples[0], 0.0, 0.0),
+            ColorModel::YCbCr => self.transform(samples[0], samples[1], samples[2]),
+            _ => self.cmyk_levels(color_model, samples, precision),
+        };
+        let max = max_level(precision);
+        let to_u16 = |v: f32| v.max(0.0).min(max).round() as u16;
+        (to_u16(rgb[0]), to_u16(rgb[1]), to_u16(rgb[2]))
+    }
+
+    // the RGB levels of a four plane pixel before rounding
+    fn cmyk_levels(&self, color_model: ColorModel, samples: [f32; 4], precision: u8) -> [f32; 3] {
+        let max = max_level(precision);
+        // the samples are level shifted and clamped to the image's precision,
+        // as libjpeg does after the iDCT
+        let level = |s: f32| (s + (max + 1.0) / 2.0).max(0.0).min(max);
+        let mut cmy = match color_model {
+            ColorModel::Ycck => {
+                let rgb = self.transform(samples[0], samples[1], samples[2]);
+                [max - rgb[0].max(0.0).min(max),
+                 max - rgb[1].max(0.0).min(max),
+                 max - rgb[2].max(0.0).min(max)]
+            },
+            _ => [level(samples[0]), level(samples[1]), level(samples[2])],
+        };
+        let mut k = level(samples[3]);
+        // work with the inverted values, which are how much light gets through
+        if color_model == ColorModel::Cmyk {
+            cmy = [max - cmy[0], max - cmy[1], max - cmy[2]];
+            k = max - k;
+        }
+        [cmy[0] * k / max, cmy[1] * k / max, cmy[2] * k / max]
+    }
+}
+
+// rounds and saturates as writing to an 8-bit texture does
+fn to_u8(v: f32) -> u8 {
+    v.max(0.0).min(255.0).round() as u8
+}
+
+// the sample of a plane under the pixel at (x, y), as the conversion shaders
+// find it. samples outside the part of the plane the image covers are clamped
+// to its edge.
+fn upsample(samples: &[i16], stride: u32, region: &Region, covered: (u32, u32),
+            max_sampling: (u32, u32), x: u32, y: u32, upsampling: Upsampling) -> f32 {
+    let (h, v) = (region.h as u32, region.v as u32);
+    let (h_max, v_max) = max_sampling;
+    let fetch = |i: i32, j: i32| {
+        let i = i.max(0).min(covered.0 as i32 - 1) as u32;
+        let j = j.max(0).min(covered.1 as i32 - 1) as u32;
+        samples[((region.y + j) * stride + region.x + i) as usize] as i32
+    };
+    let nearest = || fetch((x * h / h_max) as i32, (y * v / v_max) as i32) as f32;
+
+    match upsampling {
+        Upsampling::Nearest => nearest(),
+        Upsampling::Bilinear => {
+            let cx = (x as f32 + 0.5) * h as f32 / h_max as f32 - 0.5;
+            let cy = (y as f32 + 0.5) * v as f32 / v_max as f32 - 0.5;
+            let (x0, y0) = (cx.floor(), cy.floor());
+            let (fx, fy) = (cx - x0, cy - y0);
+            let (x0, y0) = (x0 as i32, y0 as i32);
+            let mix = |a: f32, b: f32, t: f32| a * (1.0 - t) + b * t;
+            let top = mix(fetch(x0, y0) as f32, fetch(x0 + 1, y0) as f32, fx);
+            let bot = mix(fetch(x0, y0 + 1) as f32, fetch(x0 + 1, y0 + 1) as f32, fx);
+            mix(top, bot, fy)
+        },
+        Upsampling::Fancy => {
+            let (i, j) = ((x >> 1) as i32, (y >> 1) as i32);
+            // the neighbor on the same side of the nearest sample as we are
+            let ni = if x & 1 == 1 { i + 1 } else { i - 1 };
+            let nj = if y & 1 == 1 { j + 1 } else { j - 1 };
+            let value = match (h_max == 2 * h, v_max == 2 * v) {
+                (true, true) => {
+                    let this = 3 * fetch(i, j) + fetch(i, nj);
+                    let other = 3 * fetch(ni, j) + fetch(ni, nj);
+                    (3 * this + other + if x & 1 == 1 { 7 } else { 8 }) >> 4
+                },
+                (true, false) => {
+                    let j = (y * v / v_max) as i32;
+                    (3 * fetch(i, j) + fetch(ni, j) + if x & 1 == 1 { 2 } else { 1 }) >> 2
+                },
+                (false, true) => {
+                    let i = (x * h / h_max) as i32;
+                    (3 * fetch(i, j) + fetch(i, nj) + if y & 1 == 1 { 2 } else { 1 }) >> 2
+                },
+                (false, false) => return nearest(),
+            };
+            value as f32
+        },
+    }
+}
+
+// the CPU equivalent of Decoder::convert_planes, taking the samples of a
+// decoded 8-bit atlas and returning RGB8 pixels of the true image size with the
+// top row first
+pub fn convert(width: u32, height: u32, atlas: &Atlas, samples: &[i16], matrix: &ColorMatrix,
+               upsampling: Upsampling) -> Vec<u8> {
+    assert!(atlas.precision == 8, "use convert_u16 for more than 8 bits per sample");
+    convert_pixels(width, height, atlas, samples, upsampling, |model, samples| {
+        match model {
+            ColorModel::Gray => matrix.apply(samples[0], 0.0, 0.0),
+            ColorModel::YCbCr => matrix.apply(samples[0], samples[1], samples[2]),
+            model => matrix.apply_cmyk(model, samples),
+        }
+    })
+}
+
+// like convert but for atlases of any precision, returning RGB pixels with
+// levels up to max_level(atlas.precision). the matrix must have been made for
+// the atlas's precision.
+pub fn convert_u16(width: u32, height: u32, atlas: &Atlas, samples: &[i16],
+                   matrix: &ColorMatrix, upsampling: Upsampling) -> Vec<u16> {
+    convert_pixels(width, height, atlas, samples, upsampling, |model, samples| {
+        matrix.apply_u16(model, samples, atlas.precision)
+    })
+}
+
+// upsamples every plane for each pixel, converting them with the given
+// function. planes the color model doesn't have are zero.
+fn convert_pixels<T, F>(width: u32, height: u32, atlas: &Atlas, samples: &[i16],
+                        upsampling: Upsampling, pixel: F) -> Vec<T>
+    where F: Fn(ColorModel, [f32; 4]) -> (T, T, T) {
+    assert!(samples.len() == (atlas.width * atlas.height) as usize);
+    let regions = &atlas.regions;
+    let h_max = regions.iter().map(|r| r.h).max().unwrap();
+    let v_max = regions.iter().map(|r| r.v).max().unwrap();
+    let covered: Vec<_> = regions.iter()
+        .map(|r| r.covered_dims(width, height, h_max, v_max))
+        .collect();
+    let max_sampling = (h_max as u32, v_max as u32);
+    let sample = |plane: usize, x: u32, y: u32| {
+        upsample(samples, atlas.width, &regions[plane], covered[plane], max_sampling,
+                 x, y, upsampling)
+    };
+
+    let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let mut values = [0.0; 4];
+            for (plane, value) in values.iter_mut().enumerate().take(regions.len()) {
+                *value = sample(plane, x, y);
+            }
+            let (r, g, b) = pixel(atlas.color_model, values);
+            pixels.push(r);
+            pixels.push(g);
+            pixels.push(b);
+        }
+    }
+    pixels
+}
@@ -0,0 +1,299 @@
+// the .gpeg container stores every plane of an image already packed, so the
+// planes can be uploaded to the GPU as soon as they're read. all values are
+// little endian.
+//
+// file header (24 bytes)
+//   0  magic "GPEG"
+//   4  version (u16)
+//   6  color model (u8)
+//   7  number of planes (u8)
+//   8  image width (u32)
+//  12  image height (u32)
+//  16  alignment of the packed coefficient streams (u32)
+//  20  bits per sample, 8 or 12 (u8)
+//  21  reserved, must be zero (3 bytes)
+//
+// followed by a header for each plane (148 bytes)
+//   0  plane width (u32)
+//   4  plane height (u32)
+//   8  horizontal sampling factor (u8)
+//   9  vertical sampling factor (u8)
+//  10  reserved, must be zero (u16)
+//  12  number of packed coefficients (u32)
+//  16  number of block indices (u32)
+//  20  quantization table in natural order (64 u16s)
+//
+// and then the packed coefficients (u16) and block indices (u32) of each plane
+// in turn.
+
+use binio::{read_exact, read_u16, read_u32, write_u16, write_u32};
+use markers::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use {packed_block_end, Plane, PACK_ALIGNMENT};
+
+const MAGIC: &'static [u8; 4] = b"GPEG";
+const VERSION: u16 = 1;
+
+// largest chunk of values converted at once while streaming
+const CHUNK_SIZE: usize = 4096;
+
+// how the planes combine into color
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColorModel {
+    Gray,
+    YCbCr,
+    // amounts of each ink
+    Cmyk,
+    // CMYK as Adobe writes it, with each value being 255 less the amount of ink
+    InvertedCmyk,
+    // inverted CMYK with the C, M and Y planes transformed as YCbCr would be
+    Ycck,
+}
+
+impl ColorModel {
+    fn from_u8(value: u8) -> Option<ColorModel> {
+        match value {
+            0 => Some(ColorModel::Gray),
+            1 => Some(ColorModel::YCbCr),
+            2 => Some(ColorModel::Cmyk),
+            3 => Some(ColorModel::InvertedCmyk),
+            4 => Some(ColorModel::Ycck),
+            _ => None,
+        }
+    }
+
+    fn to_u8(&self) -> u8 {
+        match *self {
+            ColorModel::Gray => 0,
+            ColorModel::YCbCr => 1,
+            ColorModel::Cmyk => 2,
+            ColorModel::InvertedCmyk => 3,
+            ColorModel::Ycck => 4,
+        }
+    }
+
+    pub fn num_planes(&self) -> usize {
+        match *self {
+            ColorModel::Gray => 1,
+            ColorModel::YCbCr => 3,
+            ColorModel::Cmyk | ColorModel::InvertedCmyk | ColorModel::Ycck => 4,
+        }
+    }
+}
+
+// width and height are the true image dimensions, which the planes may be
+// padded past
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub color_model: ColorModel,
+    // bits per sample
+    pub precision: u8,
+    pub planes: Vec<Plane>,
+}
+
+struct PlaneHeader {
+    width: u32,
+    height: u32,
+    h: u8,
+    v: u8,
+    num_coeffs: usize,
+    num_indices: usize,
+    quant_table: Vec<u16>,
+}
+
+fn read_plane_header(input: &mut Read, alignment: usize) -> Result<PlaneHeader, Error> {
+    let mut header = [0; 148];
+    try!(read_exact(input, &mut header));
+    let width = read_u32(&header[0..4]);
+    let height = read_u32(&header[4..8]);
+    let (h, v) = (header[8], header[9]);
+    if read_u16(&header[10..12]) != 0 {
+        return Err(Error::Malformed("plane header"));
+    }
+    let num_coeffs = read_u32(&header[12..16]) as usize;
+    let num_indices = read_u32(&header[16..20]) as usize;
+    let quant_table: Vec<u16> = header[20..148].chunks(2).map(read_u16).collect();
+
+    if width == 0 || height == 0 || width % 8 != 0 || height % 8 != 0 {
+        return Err(Error::Malformed("plane dimensions"));
+    }
+    if h < 1 || h > 4 || v < 1 || v > 4 {
+        return Err(Error::Malformed("plane sampling factors"));
+    }
+    if num_indices != (width as usize >> 3) * (height as usize >> 3) {
+        return Err(Error::Malformed("number of block indices"));
+    }
+    if num_coeffs % alignment != 0 {
+        return Err(Error::Malformed("packed coefficient alignment"));
+    }
+    if quant_table.iter().any(|&q| q == 0) {
+        return Err(Error::Malformed("zero in quantization table"));
+    }
+    Ok(PlaneHeader {
+        width: width,
+        height: height,
+        h: h,
+        v: v,
+        num_coeffs: num_coeffs,
+        num_indices: num_indices,
+        quant_table: quant_table,
+    })
+}
+
+// the counts come straight from the headers, so the values are only allocated
+// as they're actually read
+fn read_u16s(input: &mut Read, len: usize) -> Result<Vec<u16>, Error> {
+    let mut values = Vec::with_capacity(len.min(CHUNK_SIZE));
+    let mut buf = [0; CHUNK_SIZE * 2];
+    while values.len() < len {
+        let n = (len - values.len()).min(CHUNK_SIZE);
+        try!(read_exact(input, &mut buf[..n * 2]));
+        values.extend(buf[..n * 2].chunks(2).map(read_u16));
+    }
+    Ok(values)
+}
+
+fn read_u32s(input: &mut Read, len: usize) -> Result<Vec<u32>, Error> {
+    let mut values = Vec::with_capacity(len.min(CHUNK_SIZE));
+    let mut buf = [0; CHUNK_SIZE * 4];
+    while values.len() < len {
+        let n = (len - values.len()).min(CHUNK_SIZE);
+        try!(read_exact(input, &mut buf[..n * 4]));
+        values.extend(buf[..n * 4].chunks(4).map(read_u32));
+    }
+    Ok(values)
+}
+
+pub fn read(input: &mut Read) -> Result<Image, Error> {
+    let mut header = [0; 24];
+    try!(read_exact(input, &mut header));
+    if &header[0..4] != &MAGIC[..] {
+        return Err(Error::Malformed("not a gpeg file"));
+    }
+    if read_u16(&header[4..6]) != VERSION {
+        return Err(Error::Unsupported("gpeg version"));
+    }
+    let color_model = try!(ColorModel::from_u8(header[6])
+                           .ok_or(Error::Unsupported("color model")));
+    let num_planes = header[7] as usize;
+    let width = read_u32(&header[8..12]);
+    let height = read_u32(&header[12..16]);
+    let alignment = read_u32(&header[16..20]) as usize;
+    let precision = header[20];
+    if precision != 8 && precision != 12 {
+        return Err(Error::Unsupported("sample precision"));
+    }
+    if header[21..24].iter().any(|&b| b != 0) {
+        return Err(Error::Malformed("file header"));
+    }
+    if num_planes != color_model.num_planes() {
+        return Err(Error::Malformed("number of planes"));
+    }
+    if width == 0 || height == 0 {
+        return Err(Error::Malformed("image dimensions"));
+    }
+    if alignment == 0 {
+        return Err(Error::Malformed("packed coefficient alignment"));
+    }
+
+    let mut headers = vec![];
+    for _ in 0..num_planes {
+        headers.push(try!(read_plane_header(input, alignment)));
+    }
+
+    let mut planes = vec![];
+    for header in headers {
+        let packed_coeffs = try!(read_u16s(input, header.num_coeffs));
+        let packed_indices = try!(read_u32s(input, header.num_indices));
+        // each index must point just past the block before it, and the last
+        // block must only be followed by the padding out to the alignment
+        let mut end = 0;
+        for &index in packed_indices.iter() {
+            if index as usize != end {
+                return Err(Error::Malformed("block index out of place"));
+            }
+            end = try!(packed_block_end(&packed_coeffs, end)
+                       .ok_or(Error::Malformed("packed block runs past its end")));
+        }
+        if (end + alignment - 1) / alignment * alignment != header.num_coeffs {
+            return Err(Error::Malformed("number of packed coefficients"));
+        }
+        planes.push(Plane {
+            width: header.width,
+            height: header.height,
+            h: header.h,
+            v: header.v,
+            packed_coeffs: packed_coeffs,
+            packed_indices: packed_indices,
+            quant_table: header.quant_table,
+        });
+    }
+
+    Ok(Image {
+        width: width,
+        height: height,
+        color_model: color_model,
+        precision: precision,
+        planes: planes,
+    })
+}
+
+pub fn write(output: &mut Write, image: &Image) -> io::Result<()> {
+    assert!(image.planes.len() == image.color_model.num_planes());
+    let mut header = [0; 24];
+    header[0..4].copy_from_slice(MAGIC);
+    write_u16(&mut header[4..6], VERSION);
+    header[6] = image.color_model.to_u8();
+    header[7] = image.planes.len() as u8;
+    write_u32(&mut header[8..12], image.width);
+    write_u32(&mut header[12..16], image.height);
+    write_u32(&mut header[16..20], PACK_ALIGNMENT as u32);
+    header[20] = image.precision;
+    try!(output.write_all(&header));
+
+    for plane in image.planes.iter() {
+        assert!(plane.quant_table.len() == 64);
+        let mut header = [0; 148];
+        write_u32(&mut header[0..4], plane.width);
+        write_u32(&mut header[4..8], plane.height);
+        header[8] = plane.h;
+        header[9] = plane.v;
+        write_u32(&mut header[12..16], plane.packed_coeffs.len() as u32);
+        write_u32(&mut header[16..20], plane.packed_indices.len() as u32);
+        for (i, &q) in plane.quant_table.iter().enumerate() {
+            write_u16(&mut header[20 + i * 2..], q);
+        }
+        try!(output.write_all(&header));
+    }
+
+    for plane in image.planes.iter() {
+        let mut buf = [0; CHUNK_SIZE * 4];
+        for chunk in plane.packed_coeffs.chunks(CHUNK_SIZE) {
+            for (i, &value) in chunk.iter().enumerate() {
+                write_u16(&mut buf[i * 2..], value);
+            }
+            try!(output.write_all(&buf[..chunk.len() * 2]));
+        }
+        for chunk in plane.packed_indices.chunks(CHUNK_SIZE) {
+            for (i, &value) in chunk.iter().enumerate() {
+                write_u32(&mut buf[i * 4..], value);
+            }
+            try!(output.write_all(&buf[..chunk.len() * 4]));
+        }
+    }
+    Ok(())
+}
+
+pub fn read_file(path: &str) -> Result<Image, Error> {
+    let f = try!(File::open(path));
+    read(&mut BufReader::new(f))
+}
+
+pub fn write_file(path: &str, image: &Image) -> io::Result<()> {
+    let f = try!(File::create(path));
+    let mut output = BufWriter::new(f);
+    try!(write(&mut output, image));
+    output.flush()
+}
@@ -1,27 +1,41 @@
-#version 140
+// the decoder puts upsample.glsl in front of this, with the version line
 
 in vec2 v_tex_coords;
 
 out vec3 color;
 
 uniform ivec2 plane_dims;
-uniform isampler2D y_plane;
-uniform isampler2D cb_plane;
-uniform isampler2D cr_plane;
+uniform ivec2 y_sampling;
+uniform ivec2 cb_sampling;
+uniform ivec2 cr_sampling;
+uniform mat3 color_matrix;
+uniform vec3 color_offset;
+// the largest level a sample of the image's precision can have
+uniform float max_level;
+// every plane is in the one atlas texture, each with its own origin
+uniform ivec2 y_origin;
+uniform ivec2 cb_origin;
+uniform ivec2 cr_origin;
+// and only the part of each plane the image covers is used
+uniform ivec2 y_covered;
+uniform ivec2 cb_covered;
+uniform ivec2 cr_covered;
+uniform isampler2D planes;
 
-void main() {
-  ivec2 y_tex_coords = ivec2(v_tex_coords * plane_dims);
-  // one shift for decimated color plane, and one for the data being on only
-  // one quadrant of the texture
-  ivec2 c_tex_coords = y_tex_coords >> 2;
+// fetches a sample of the plane at origin, clamping to its edges
+int fetch(ivec2 origin, ivec2 covered, ivec2 p) {
+  return texelFetch(planes, origin + clamp(p, ivec2(0), covered - 1), 0).r;
+}
 
-  float y = float(texelFetch(y_plane, y_tex_coords, 0).r) + 128;
-  float cb = float(texelFetch(cb_plane, c_tex_coords, 0).r);
-  float cr = float(texelFetch(cr_plane, c_tex_coords, 0).r);
+void main() {
+  ivec2 tex_coords = ivec2(v_tex_coords * plane_dims);
+  // each plane covers the image at its own fraction of the full resolution
+  float y = upsample(tex_coords, y_origin, y_covered, y_sampling);
+  float cb = upsample(tex_coords, cb_origin, cb_covered, cb_sampling);
+  float cr = upsample(tex_coords, cr_origin, cr_covered, cr_sampling);
 
-  float r = y + 1.402 * cr;
-  float g = y - 0.34414 * cb - 0.71414 * cr;
-  float b = y + 1.772 * cb;
+  // the matrix and offset take care of the level shift as well
+  vec3 rgb = color_matrix * vec3(y, cb, cr) + color_offset;
 
-  color = vec3(r / 255, g / 255, b / 255);
+  color = rgb / max_level;
 }
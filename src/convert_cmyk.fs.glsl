@@ -0,0 +1,64 @@
+// the decoder puts upsample.glsl in front of this, with the version line
+
+// the color models with four planes
+const int CMYK = 0;
+const int INVERTED_CMYK = 1;
+const int YCCK = 2;
+
+in vec2 v_tex_coords;
+
+out vec3 color;
+
+uniform ivec2 plane_dims;
+uniform int color_model;
+// for YCCK the first three planes are Y, Cb and Cr, which the matrix turns
+// back into inverted C, M and Y
+uniform mat3 color_matrix;
+uniform vec3 color_offset;
+// the largest level a sample of the image's precision can have
+uniform float max_level;
+uniform ivec2 c_sampling;
+uniform ivec2 m_sampling;
+uniform ivec2 y_sampling;
+uniform ivec2 k_sampling;
+// every plane is in the one atlas texture, each with its own origin
+uniform ivec2 c_origin;
+uniform ivec2 m_origin;
+uniform ivec2 y_origin;
+uniform ivec2 k_origin;
+// and only the part of each plane the image covers is used
+uniform ivec2 c_covered;
+uniform ivec2 m_covered;
+uniform ivec2 y_covered;
+uniform ivec2 k_covered;
+uniform isampler2D planes;
+
+// fetches a sample of the plane at origin, clamping to its edges
+int fetch(ivec2 origin, ivec2 covered, ivec2 p) {
+  return texelFetch(planes, origin + clamp(p, ivec2(0), covered - 1), 0).r;
+}
+
+void main() {
+  ivec2 tex_coords = ivec2(v_tex_coords * plane_dims);
+  vec4 samples = vec4(upsample(tex_coords, c_origin, c_covered, c_sampling),
+                      upsample(tex_coords, m_origin, m_covered, m_sampling),
+                      upsample(tex_coords, y_origin, y_covered, y_sampling),
+                      upsample(tex_coords, k_origin, k_covered, k_sampling));
+
+  // the samples are level shifted and clamped to the image's precision, as
+  // libjpeg does after the iDCT
+  vec4 levels = clamp(samples + (max_level + 1.0) / 2.0, 0.0, max_level);
+  vec3 cmy = levels.rgb;
+  float k = levels.a;
+  if (color_model == YCCK) {
+    cmy = max_level - clamp(color_matrix * samples.rgb + color_offset, 0.0, max_level);
+  }
+
+  // work with the inverted values, which are how much light gets through
+  if (color_model == CMYK) {
+    cmy = max_level - cmy;
+    k = max_level - k;
+  }
+
+  color = cmy * k / max_level / max_level;
+}
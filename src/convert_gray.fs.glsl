@@ -0,0 +1,24 @@
+#version 140
+
+in vec2 v_tex_coords;
+
+out vec3 color;
+
+uniform ivec2 plane_dims;
+uniform mat3 color_matrix;
+uniform vec3 color_offset;
+// the largest level a sample of the image's precision can have
+uniform float max_level;
+uniform ivec2 y_origin;
+uniform isampler2D planes;
+
+void main() {
+  ivec2 y_tex_coords = y_origin + ivec2(v_tex_coords * plane_dims);
+
+  float y = float(texelFetch(planes, y_tex_coords, 0).r);
+
+  // with no chroma only the luma scale and offset matter
+  vec3 rgb = color_matrix * vec3(y, 0, 0) + color_offset;
+
+  color = rgb / max_level;
+}
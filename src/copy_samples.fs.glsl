@@ -0,0 +1,14 @@
+#version 140
+
+in vec2 v_tex_coords;
+
+out int color;
+
+uniform ivec2 plane_dims;
+uniform isamplerBuffer samples;
+
+// copies the output of the compute shader into a plane texture
+void main() {
+  ivec2 i_tex_coords = ivec2(v_tex_coords * plane_dims);
+  color = texelFetch(samples, i_tex_coords.y * plane_dims.x + i_tex_coords.x).r;
+}
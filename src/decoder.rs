@@ -0,0 +1,690 @@
+use glium;
+use glium::Surface;
+use glium::backend::Facade;
+use std::borrow::Cow;
+use std::rc::Rc;
+use atlas::Atlas;
+use color::{self, ColorMatrix, ColorRange, ColorSpace, Upsampling};
+use container::ColorModel;
+use idct;
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+}
+
+implement_vertex!(Vertex, position, tex_coords);
+
+// how the packed coefficients are turned back into blocks
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnpackMode {
+    // every pixel walks its block's coefficients to find its own
+    Pixel,
+    // every block row walks its block's coefficients once, feeding the first
+    // iDCT pass directly
+    Row,
+}
+
+// which kind of shaders do the unpacking and iDCT
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Backend {
+    // fullscreen fragment passes through intermediate textures
+    Fragment,
+    // a compute shader with one workgroup per block, which needs GL 4.3
+    Compute,
+}
+
+// how the decoded planes are turned into RGB
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ConvertMode {
+    // a separate pass reads every plane and converts each pixel
+    Shader,
+    // the last iDCT pass draws each plane over the output with additive
+    // blending, adding in its share of each color channel
+    Blend,
+}
+
+// runs the unpack, iDCT and color conversion shaders on any glium facade, be it
+// a window, a headless context or a software rasterizer
+pub struct Decoder {
+    facade: Rc<glium::backend::Context>,
+    width: u32,
+    height: u32,
+    backend: Backend,
+    unpack_mode: UnpackMode,
+    convert_mode: ConvertMode,
+    color_space: ColorSpace,
+    color_range: ColorRange,
+    upsampling: Upsampling,
+    vertices: glium::vertex::VertexBuffer<Vertex>,
+    program_unpack: glium::program::Program,
+    program_unpack_rows: glium::program::Program,
+    program_pass1: glium::program::Program,
+    program_pass2: glium::program::Program,
+    program_pass3: glium::program::Program,
+    program_pass3_blend: glium::program::Program,
+    program_convert: glium::program::Program,
+    program_convert_gray: glium::program::Program,
+    program_convert_cmyk: glium::program::Program,
+    program_compute: Option<glium::program::ComputeShader>,
+    program_copy_samples: Option<glium::program::Program>,
+    pass1_top: glium::texture::IntegralTexture2d,
+    pass1_bot: glium::texture::IntegralTexture2d,
+    pass2_top: glium::texture::IntegralTexture2d,
+    pass2_bot: glium::texture::IntegralTexture2d,
+}
+
+impl Decoder {
+    // width and height are those of the largest atlas to be decoded, whose
+    // planes are padded out to whole MCUs and so may be larger than the image
+    pub fn new<F: Facade>(facade: &F, width: u32, height: u32) -> Decoder {
+        Decoder::with_backend(facade, width, height, Backend::Fragment).unwrap()
+    }
+
+    // returns None if the context doesn't support the backend
+    pub fn with_backend<F: Facade>(facade: &F, width: u32, height: u32, backend: Backend)
+                                   -> Option<Decoder> {
+        let facade = facade.get_context().clone();
+        if backend == Backend::Compute && !glium::program::ComputeShader::is_supported(&*facade) {
+            return None;
+        }
+
+        let v1 = Vertex { position: [-1.0, -1.0], tex_coords: [0.0, 0.0] };
+        let v2 = Vertex { position: [-1.0, 1.0], tex_coords: [0.0, 1.0] };
+        let v3 = Vertex { position: [1.0, -1.0], tex_coords: [1.0, 0.0] };
+        let v4 = Vertex { position: [1.0, 1.0], tex_coords: [1.0, 1.0] };
+        let strip = vec![v1, v2, v3, v4];
+        let vertices = glium::VertexBuffer::new(&facade, &strip).unwrap();
+
+        let vertex_shader_src = include_str!("thru.vs.glsl");
+        let fragment_shader_unpack_src = include_str!("unpack.fs.glsl");
+        let fragment_shader_unpack_rows_src = include_str!("unpack_rows.fs.glsl");
+        let fragment_shader_pass1_src = include_str!("idct8x8_pass1.fs.glsl");
+        let fragment_shader_pass2_src = include_str!("idct8x8_pass2.fs.glsl");
+        let fragment_shader_pass3_src = include_str!("idct8x8_pass3.fs.glsl");
+        // the shaders that upsample planes share it, so it goes in front of each
+        let upsample_src = include_str!("upsample.glsl");
+        let fragment_shader_pass3_blend_src =
+            format!("{}{}", upsample_src, include_str!("idct8x8_pass3_blend.fs.glsl"));
+        let fragment_shader_convert_src =
+            format!("{}{}", upsample_src, include_str!("convert.fs.glsl"));
+        let fragment_shader_convert_gray_src = include_str!("convert_gray.fs.glsl");
+        let fragment_shader_convert_cmyk_src =
+            format!("{}{}", upsample_src, include_str!("convert_cmyk.fs.glsl"));
+        let program_unpack = program!(
+            &facade,
+            140 => {
+                vertex: vertex_shader_src,
+                fragment: fragment_shader_unpack_src,
+            }
+        ).unwrap();
+        let program_unpack_rows = program!(
+            &facade,
+            140 => {
+                vertex: vertex_shader_src,
+                fragment: fragment_shader_unpack_rows_src,
+            }
+        ).unwrap();
+        let program_pass1 = program!(
+            &facade,
+            140 => {
+                vertex: vertex_shader_src,
+                fragment: fragment_shader_pass1_src,
+            }
+        ).unwrap();
+        let program_pass2 = program!(
+            &facade,
+            140 => {
+                vertex: vertex_shader_src,
+                fragment: fragment_shader_pass2_src,
+            }
+        ).unwrap();
+        let program_pass3 = program!(
+            &facade,
+            140 => {
+                vertex: vertex_shader_src,
+                fragment: fragment_shader_pass3_src,
+            }
+        ).unwrap();
+        let program_pass3_blend = program!(
+            &facade,
+            140 => {
+                vertex: vertex_shader_src,
+                fragment: &fragment_shader_pass3_blend_src,
+            }
+        ).unwrap();
+        let program_convert = program!(
+            &facade,
+            140 => {
+                vertex: vertex_shader_src,
+                fragment: &fragment_shader_convert_src,
+            }
+        ).unwrap();
+        let program_convert_gray = program!(
+            &facade,
+            140 => {
+                vertex: vertex_shader_src,
+                fragment: fragment_shader_convert_gray_src,
+            }
+        ).unwrap();
+        let program_convert_cmyk = program!(
+            &facade,
+            140 => {
+                vertex: vertex_shader_src,
+                fragment: &fragment_shader_convert_cmyk_src,
+            }
+        ).unwrap();
+
+        let (program_compute, program_copy_samples) = if backend == Backend::Compute {
+            let compute_shader_src = include_str!("idct8x8.cs.glsl");
+            let fragment_shader_copy_samples_src = include_str!("copy_samples.fs.glsl");
+            let program_compute = glium::program::ComputeShader::from_source(
+                &facade,
+                compute_shader_src).unwrap();
+            let program_copy_samples = program!(
+                &facade,
+                140 => {
+                    vertex: vertex_shader_src,
+                    fragment: fragment_shader_copy_samples_src,
+                }
+            ).unwrap();
+            (Some(program_compute), Some(program_copy_samples))
+        } else {
+            (None, None)
+        };
+
+        // the intermediate textures can be width/8 because we only need hte
+        // first column of each block
+        let pass1_top = glium::texture::IntegralTexture2d::empty_with_format(
+            &facade,
+            glium::texture::UncompressedIntFormat::I16I16I16I16,
+            glium::texture::MipmapsOption::NoMipmap,
+            width / 8, height).unwrap();
+        let pass1_bot = glium::texture::IntegralTexture2d::empty_with_format(
+            &facade,
+            glium::texture::UncompressedIntFormat::I16I16I16I16,
+            glium::texture::MipmapsOption::NoMipmap,
+            width / 8, height).unwrap();
+        let pass2_top = glium::texture::IntegralTexture2d::empty_with_format(
+            &facade,
+            glium::texture::UncompressedIntFormat::I16I16I16I16,
+            glium::texture::MipmapsOption::NoMipmap,
+            width / 8, height).unwrap();
+        let pass2_bot = glium::texture::IntegralTexture2d::empty_with_format(
+            &facade,
+            glium::texture::UncompressedIntFormat::I16I16I16I16,
+            glium::texture::MipmapsOption::NoMipmap,
+            width / 8, height).unwrap();
+
+        Some(Decoder {
+            facade: facade,
+            width: width,
+            height: height,
+            backend: backend,
+            unpack_mode: UnpackMode::Pixel,
+            convert_mode: ConvertMode::Shader,
+            color_space: ColorSpace::Bt601,
+            color_range: ColorRange::Full,
+            upsampling: Upsampling::Nearest,
+            vertices: vertices,
+            program_unpack: program_unpack,
+            program_unpack_rows: program_unpack_rows,
+            program_pass1: program_pass1,
+            program_pass2: program_pass2,
+            program_pass3: program_pass3,
+            program_pass3_blend: program_pass3_blend,
+            program_convert: program_convert,
+            program_convert_gray: program_convert_gray,
+            program_convert_cmyk: program_convert_cmyk,
+            program_compute: program_compute,
+            program_copy_samples: program_copy_samples,
+            pass1_top: pass1_top,
+            pass1_bot: pass1_bot,
+            pass2_top: pass2_top,
+            pass2_bot: pass2_bot,
+        })
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    // only the fragment backend has a choice of unpack modes
+    pub fn set_unpack_mode(&mut self, mode: UnpackMode) {
+        self.unpack_mode = mode;
+    }
+
+    // only the fragment backend can blend, since the compute backend doesn't
+    // have a last pass to do it in, and only gray and YCbCr images can be
+    // blended
+    pub fn set_convert_mode(&mut self, mode: ConvertMode) {
+        self.convert_mode = mode;
+    }
+
+    // the default is full range BT.601, as JFIF uses
+    pub fn set_color_space(&mut self, space: ColorSpace) {
+        self.color_space = space;
+    }
+
+    pub fn set_color_range(&mut self, range: ColorRange) {
+        self.color_range = range;
+    }
+
+    pub fn set_upsampling(&mut self, upsampling: Upsampling) {
+        self.upsampling = upsampling;
+    }
+
+    fn color_matrix(&self, precision: u8) -> ColorMatrix {
+        ColorMatrix::with_precision(self.color_space, self.color_range, precision)
+    }
+
+    // the values of the conversion shaders' upsampling constants
+    fn upsampling_mode(&self) -> i32 {
+        match self.upsampling {
+            Upsampling::Nearest => 0,
+            Upsampling::Bilinear => 1,
+            Upsampling::Fancy => 2,
+        }
+    }
+
+    // unpacks, dequantizes and inverse transforms every plane in the atlas at
+    // once, returning the samples without the level shift applied
+    pub fn decode_atlas(&self, atlas: &Atlas) -> glium::texture::IntegralTexture2d {
+        let output = self.run_passes(atlas);
+        if self.backend == Backend::Compute {
+            return output;
+        }
+
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+        let uniforms_pass3 = uniform! {
+            plane_dims: [atlas.width as i32, atlas.height as i32],
+            pass2_top: &self.pass2_top,
+            pass2_bot: &self.pass2_bot,
+            headroom: idct::headroom(atlas.precision) as i32,
+        };
+        {
+            let mut target3 = glium::framebuffer::SimpleFrameBuffer::new(
+                &self.facade,
+                &output).unwrap();
+            target3.draw(&self.vertices, &indices, &self.program_pass3, &uniforms_pass3,
+                         &Default::default()).unwrap();
+        }
+        output
+    }
+
+    // runs every pass but the last iDCT pass, leaving its input in pass2_top
+    // and pass2_bot. the compute backend has no such passes and returns the
+    // finished samples instead.
+    fn run_passes(&self, atlas: &Atlas) -> glium::texture::IntegralTexture2d {
+        assert!(atlas.width <= self.width && atlas.height <= self.height,
+                "atlas is larger than the decoder");
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+        let vertices = &self.vertices;
+        let atlas_dims = [atlas.width as i32, atlas.height as i32];
+        let plane_rows = atlas.region_rows();
+        let headroom = idct::headroom(atlas.precision) as i32;
+
+        // the intermediate textures may be larger than the atlas, in which
+        // case it only renders into the corner of them that it needs
+        let params_intermediate = glium::DrawParameters {
+            viewport: Some(glium::Rect {
+                left: 0,
+                bottom: 0,
+                width: atlas.width / 8,
+                height: atlas.height,
+            }),
+            .. Default::default()
+        };
+
+        let index_image = glium::texture::RawImage2d {
+            data: Cow::Borrowed(&atlas.packed_indices),
+            width: atlas.width >> 3,
+            height: atlas.height >> 3,
+            format: glium::texture::ClientFormat::U32,
+        };
+        let index_texture = glium::texture::UnsignedTexture2d::with_format(
+            &self.facade,
+            index_image,
+            glium::texture::UncompressedUintFormat::U32,
+            glium::texture::MipmapsOption::NoMipmap).unwrap();
+
+        // the tables are stacked one above the other like the planes are
+        let quant_image = glium::texture::RawImage2d {
+            data: Cow::Borrowed(&atlas.quant_tables),
+            width: 8,
+            height: 8 * atlas.regions.len() as u32,
+            format: glium::texture::ClientFormat::U16,
+        };
+        let quant_texture = glium::texture::UnsignedTexture2d::with_format(
+            &self.facade,
+            quant_image,
+            glium::texture::UncompressedUintFormat::U16,
+            glium::texture::MipmapsOption::NoMipmap).unwrap();
+
+        let packed_texture = glium::texture::buffer_texture::BufferTexture::new(
+            &self.facade,
+            &atlas.packed_coeffs,
+            glium::texture::buffer_texture::BufferTextureType::Unsigned).unwrap();
+
+        let output_unpack = glium::texture::IntegralTexture2d::empty_with_format(
+            &self.facade,
+            glium::texture::UncompressedIntFormat::I16,
+            glium::texture::MipmapsOption::NoMipmap,
+            atlas.width, atlas.height).unwrap();
+
+        if let (&Some(ref compute), &Some(ref copy_samples)) =
+            (&self.program_compute, &self.program_copy_samples) {
+            let samples: glium::texture::buffer_texture::BufferTexture<i32> =
+                glium::texture::buffer_texture::BufferTexture::empty(
+                    &self.facade,
+                    (atlas.width * atlas.height) as usize,
+                    glium::texture::buffer_texture::BufferTextureType::Integral).unwrap();
+            let uniforms_compute = uniform! {
+                plane_dims: atlas_dims,
+                plane_rows: plane_rows,
+                headroom: headroom,
+                index_texture: &index_texture,
+                quant_table: &quant_texture,
+                packed_coeffs: &packed_texture,
+                Samples: &*samples,
+            };
+            compute.execute(uniforms_compute, atlas.width / 8, atlas.height / 8, 1);
+
+            // the conversion reads planes from a texture, so copy the samples
+            // into one
+            let uniforms_copy = uniform! {
+                plane_dims: atlas_dims,
+                samples: &samples,
+            };
+            {
+                let mut target = glium::framebuffer::SimpleFrameBuffer::new(
+                    &self.facade,
+                    &output_unpack).unwrap();
+                target.draw(vertices, &indices, copy_samples, &uniforms_copy,
+                            &Default::default()).unwrap();
+            }
+            return output_unpack;
+        }
+
+        let uniforms_unpack = uniform! {
+            plane_dims: atlas_dims,
+            plane_rows: plane_rows,
+            headroom: headroom,
+            index_texture: &index_texture,
+            quant_table: &quant_texture,
+            packed_coeffs: &packed_texture,
+        };
+        {
+            let output_pass1 = [
+                ("pass1_top", &self.pass1_top),
+                ("pass1_bot", &self.pass1_bot),
+            ];
+            let mut target1 = glium::framebuffer::MultiOutputFrameBuffer::new(
+                &self.facade,
+                output_pass1.iter().cloned()).unwrap();
+            match self.unpack_mode {
+                UnpackMode::Pixel => {
+                    {
+                        let mut target_unpack = glium::framebuffer::SimpleFrameBuffer::new(
+                            &self.facade,
+                            &output_unpack).unwrap();
+                        target_unpack.draw(
+                            &self.vertices,
+                            &indices,
+                            &self.program_unpack,
+                            &uniforms_unpack,
+                            &Default::default()).unwrap();
+                    }
+                    let uniforms_pass1 = uniform! {
+                        plane_dims: atlas_dims,
+                        data: &output_unpack,
+                        headroom: headroom,
+                    };
+                    target1.draw(vertices, &indices, &self.program_pass1, &uniforms_pass1,
+                                 &params_intermediate).unwrap();
+                },
+                UnpackMode::Row => {
+                    // the unpack output texture is only used for pass 3
+                    target1.draw(vertices, &indices, &self.program_unpack_rows,
+                                 &uniforms_unpack, &params_intermediate).unwrap();
+                },
+            }
+        }
+        {
+            let uniforms_pass2 = uniform! {
+                plane_dims: atlas_dims,
+                pass1_top: &self.pass1_top,
+                pass1_bot: &self.pass1_bot,
+            };
+            let output_pass2 = [
+                ("pass2_top", &self.pass2_top),
+                ("pass2_bot", &self.pass2_bot),
+            ];
+            let mut target2 = glium::framebuffer::MultiOutputFrameBuffer::new(
+                &self.facade,
+                output_pass2.iter().cloned()).unwrap();
+            target2.draw(vertices, &indices, &self.program_pass2, &uniforms_pass2,
+                         &params_intermediate).unwrap();
+        }
+        output_unpack
+    }
+
+    // converts the planes as the atlas's color model says to. width and height
+    // are the true image dimensions, which crops off any padding the planes
+    // have. images with more than 8 bits per sample are converted into a 16-bit
+    // texture so that none of their precision is lost.
+    pub fn convert_planes(&self, width: u32, height: u32, atlas: &Atlas,
+                          texture: &glium::texture::IntegralTexture2d)
+                          -> glium::texture::Texture2d {
+        let format = if atlas.precision > 8 {
+            glium::texture::UncompressedFloatFormat::U16U16U16
+        } else {
+            glium::texture::UncompressedFloatFormat::U8U8U8
+        };
+        let output = glium::texture::Texture2d::empty_with_format(
+            &self.facade,
+            format,
+            glium::texture::MipmapsOption::NoMipmap,
+            width, height).unwrap();
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+        let regions = &atlas.regions;
+        let matrix = self.color_matrix(atlas.precision);
+        let max_level = color::max_level(atlas.precision);
+        let h_max = regions.iter().map(|r| r.h).max().unwrap();
+        let v_max = regions.iter().map(|r| r.v).max().unwrap();
+        let sampling = |i: usize| [regions[i].h as i32, regions[i].v as i32];
+        let origin = |i: usize| [regions[i].x as i32, regions[i].y as i32];
+        let covered = |i: usize| {
+            let (w, h) = regions[i].covered_dims(width, height, h_max, v_max);
+            [w as i32, h as i32]
+        };
+        {
+            let mut target = glium::framebuffer::SimpleFrameBuffer::new(&self.facade, &output).unwrap();
+            match atlas.color_model {
+                ColorModel::Gray => {
+                    // the one plane is replicated into each channel
+                    let uniforms = uniform! {
+                        plane_dims: [width as i32, height as i32],
+                        color_matrix: matrix.columns,
+                        color_offset: matrix.offset,
+                        max_level: max_level,
+                        y_origin: origin(0),
+                        planes: texture,
+                    };
+                    target.draw(&self.vertices, &indices, &self.program_convert_gray, &uniforms,
+                                &Default::default()).unwrap();
+                },
+                ColorModel::YCbCr => {
+                    let uniforms = uniform! {
+                        plane_dims: [width as i32, height as i32],
+                        max_sampling: [h_max as i32, v_max as i32],
+                        upsampling: self.upsampling_mode(),
+                        y_sampling: sampling(0),
+                        cb_sampling: sampling(1),
+                        cr_sampling: sampling(2),
+                        color_matrix: matrix.columns,
+                        color_offset: matrix.offset,
+                        max_level: max_level,
+                        y_origin: origin(0),
+                        cb_origin: origin(1),
+                        cr_origin: origin(2),
+                        y_covered: covered(0),
+                        cb_covered: covered(1),
+                        cr_covered: covered(2),
+                        planes: texture,
+                    };
+                    target.draw(&self.vertices, &indices, &self.program_convert, &uniforms,
+                                &Default::default()).unwrap();
+                },
+                model => {
+                    // the values of the CMYK conversion shader's constants
+                    let model = match model {
+                        ColorModel::InvertedCmyk => 1,
+                        ColorModel::Ycck => 2,
+                        _ => 0,
+                    };
+                    let uniforms = uniform! {
+                        plane_dims: [width as i32, height as i32],
+                        color_model: model,
+                        max_sampling: [h_max as i32, v_max as i32],
+                        upsampling: self.upsampling_mode(),
+                        color_matrix: matrix.columns,
+                        color_offset: matrix.offset,
+                        max_level: max_level,
+                        c_sampling: sampling(0),
+                        m_sampling: sampling(1),
+                        y_sampling: sampling(2),
+                        k_sampling: sampling(3),
+                        c_origin: origin(0),
+                        m_origin: origin(1),
+                        y_origin: origin(2),
+                        k_origin: origin(3),
+                        c_covered: covered(0),
+                        m_covered: covered(1),
+                        y_covered: covered(2),
+                        k_covered: covered(3),
+                        planes: texture,
+                    };
+                    target.draw(&self.vertices, &indices, &self.program_convert_cmyk, &uniforms,
+                                &Default::default()).unwrap();
+                },
+            }
+        }
+        output
+    }
+
+    // does the last iDCT pass for each plane straight into the output, with
+    // additive blending summing up the planes' contributions to each channel.
+    // the output is floating point so that the partial sums aren't clamped.
+    fn blend_planes(&self, width: u32, height: u32, atlas: &Atlas) -> glium::texture::Texture2d {
+        self.run_passes(atlas);
+
+        let output = glium::texture::Texture2d::empty_with_format(
+            &self.facade,
+            glium::texture::UncompressedFloatFormat::F32F32F32F32,
+            glium::texture::MipmapsOption::NoMipmap,
+            width, height).unwrap();
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+        let params = glium::DrawParameters {
+            blend: glium::Blend {
+                color: glium::BlendingFunction::Addition {
+                    source: glium::LinearBlendingFactor::One,
+                    destination: glium::LinearBlendingFactor::One,
+                },
+                alpha: glium::BlendingFunction::Addition {
+                    source: glium::LinearBlendingFactor::One,
+                    destination: glium::LinearBlendingFactor::One,
+                },
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+            .. Default::default()
+        };
+
+        // each plane adds its column of the matrix, and luma adds the offset
+        // too. a single plane is gray, otherwise they are Y, Cb and Cr.
+        let matrix = self.color_matrix(atlas.precision);
+        let weights = [
+            (matrix.columns[0], matrix.offset),
+            (matrix.columns[1], [0.0; 3]),
+            (matrix.columns[2], [0.0; 3]),
+        ];
+        let h_max = atlas.regions.iter().map(|r| r.h).max().unwrap();
+        let v_max = atlas.regions.iter().map(|r| r.v).max().unwrap();
+        {
+            let mut target = glium::framebuffer::SimpleFrameBuffer::new(&self.facade, &output).unwrap();
+            target.clear_color(0.0, 0.0, 0.0, 0.0);
+            for (region, &(weights, offset)) in atlas.regions.iter().zip(weights.iter()) {
+                let (covered_width, covered_height) =
+                    region.covered_dims(width, height, h_max, v_max);
+                let uniforms = uniform! {
+                    plane_dims: [width as i32, height as i32],
+                    max_sampling: [h_max as i32, v_max as i32],
+                    sampling: [region.h as i32, region.v as i32],
+                    origin: [region.x as i32, region.y as i32],
+                    covered: [covered_width as i32, covered_height as i32],
+                    upsampling: self.upsampling_mode(),
+                    weights: weights,
+                    color_offset: offset,
+                    max_level: color::max_level(atlas.precision),
+                    pass2_top: &self.pass2_top,
+                    pass2_bot: &self.pass2_bot,
+                    headroom: idct::headroom(atlas.precision) as i32,
+                };
+                target.draw(&self.vertices, &indices, &self.program_pass3_blend, &uniforms,
+                            &params).unwrap();
+            }
+        }
+        output
+    }
+
+    // decodes every plane and converts them into an RGB texture of the true
+    // image size
+    pub fn decode(&self, width: u32, height: u32, atlas: &Atlas) -> glium::texture::Texture2d {
+        // CMYK multiplies the planes together, which blending can't do
+        let linear = match atlas.color_model {
+            ColorModel::Gray | ColorModel::YCbCr => true,
+            _ => false,
+        };
+        if self.convert_mode == ConvertMode::Blend && self.backend == Backend::Fragment && linear {
+            return self.blend_planes(width, height, atlas);
+        }
+        let texture = self.decode_atlas(atlas);
+        self.convert_planes(width, height, atlas, &texture)
+    }
+
+    // like decode but reads the image back, returning RGB8 pixels with the top
+    // row first
+    pub fn decode_to_rgb(&self, width: u32, height: u32, atlas: &Atlas) -> Vec<u8> {
+        let image = self.decode(width, height, atlas);
+        let rows: Vec<Vec<(u8, u8, u8, u8)>> = image.read();
+        let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+        for row in rows {
+            for (r, g, b, _) in row {
+                pixels.push(r);
+                pixels.push(g);
+                pixels.push(b);
+            }
+        }
+        pixels
+    }
+
+    // like decode_to_rgb but keeps every bit of the image's precision,
+    // returning pixels with levels up to color::max_level(atlas.precision)
+    pub fn decode_to_rgb16(&self, width: u32, height: u32, atlas: &Atlas) -> Vec<u16> {
+        let image = self.decode(width, height, atlas);
+        let rows: Vec<Vec<(u16, u16, u16, u16)>> = image.read();
+        // the texture is normalized to the full 16 bits
+        let max_level = color::max_level(atlas.precision);
+        let level = |v: u16| (v as f32 * max_level / 65535.0).round() as u16;
+        let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+        for row in rows {
+            for (r, g, b, _) in row {
+                pixels.push(level(r));
+                pixels.push(level(g));
+                pixels.push(level(b));
+            }
+        }
+        pixels
+    }
+}
@@ -0,0 +1,185 @@
+use std::io;
+
+// a canonical huffman table as described in Annex C of the spec, using the
+// maxcode/valptr decoding procedure from F.2.2.3
+pub struct HuffmanTable {
+    maxcode: [i32; 17],
+    valptr: [i32; 17],
+    mincode: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    // counts[i] is the number of codes of length i + 1 and values are the
+    // symbols in order of increasing code length
+    pub fn new(counts: &[u8; 16], values: &[u8]) -> HuffmanTable {
+        let mut maxcode = [-1; 17];
+        let mut valptr = [0; 17];
+        let mut mincode = [0; 17];
+        let mut code = 0;
+        let mut k = 0;
+        for l in 1..17 {
+            let count = counts[l - 1] as i32;
+            if count > 0 {
+                valptr[l] = k;
+                mincode[l] = code;
+                code += count;
+                k += count;
+                maxcode[l] = code - 1;
+            }
+            code <<= 1;
+        }
+        HuffmanTable {
+            maxcode: maxcode,
+            valptr: valptr,
+            mincode: mincode,
+            values: values.to_vec(),
+        }
+    }
+
+    pub fn decode(&self, reader: &mut BitReader) -> io::Result<u8> {
+        let mut code = try!(reader.read_bit()) as i32;
+        for l in 1..17 {
+            if code <= self.maxcode[l] {
+                let idx = self.valptr[l] + code - self.mincode[l];
+                return match self.values.get(idx as usize) {
+                    Some(&v) => Ok(v),
+                    None => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                               "huffman code out of range")),
+                };
+            }
+            code = (code << 1) | (try!(reader.read_bit()) as i32);
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, "bad huffman code"))
+    }
+}
+
+// reads entropy coded data a bit at a time, removing stuffed zero bytes. when
+// a marker is hit the reader remembers it and supplies zero bits from then on
+pub struct BitReader<'a> {
+    input: &'a mut Iterator<Item=io::Result<u8>>,
+    bits: u32,
+    count: u32,
+    marker: Option<u8>,
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(input: &'a mut Iterator<Item=io::Result<u8>>) -> BitReader<'a> {
+        BitReader {
+            input: input,
+            bits: 0,
+            count: 0,
+            marker: None,
+            position: 0,
+        }
+    }
+
+    // the marker which terminated the entropy coded data, if we've seen one
+    pub fn marker(&self) -> Option<u8> {
+        self.marker
+    }
+
+    // the number of bytes consumed from the input so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    fn next_byte(&mut self) -> io::Result<u8> {
+        self.position += 1;
+        match self.input.next() {
+            Some(b) => b,
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                       "ran out of entropy coded data")),
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let byte = if self.marker.is_some() {
+            0
+        } else {
+            let b = try!(self.next_byte());
+            if b != 0xff {
+                b
+            } else {
+                let mut code = try!(self.next_byte());
+                // any number of 0xff fill bytes may precede a marker
+                while code == 0xff {
+                    code = try!(self.next_byte());
+                }
+                if code == 0x00 {
+                    0xff
+                } else {
+                    self.marker = Some(code);
+                    0
+                }
+            }
+        };
+        self.bits = (self.bits << 8) | (byte as u32);
+        self.count += 8;
+        Ok(())
+    }
+
+    // skips ahead to the next marker, passing over any extraneous bytes and
+    // stuffed zeros before it the way libjpeg does
+    fn next_marker(&mut self) -> io::Result<u8> {
+        loop {
+            if try!(self.next_byte()) != 0xff {
+                continue;
+            }
+            let mut code = try!(self.next_byte());
+            while code == 0xff {
+                code = try!(self.next_byte());
+            }
+            if code != 0x00 {
+                return Ok(code);
+            }
+        }
+    }
+
+    // discards any bits left in the current byte and consumes the expected
+    // restart marker, as happens at the end of every restart interval
+    pub fn read_restart(&mut self, n: u8) -> io::Result<()> {
+        self.bits = 0;
+        self.count = 0;
+        let code = match self.marker.take() {
+            Some(code) => code,
+            None => try!(self.next_marker()),
+        };
+        if code != 0xd0 + n {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "restart marker out of sequence"));
+        }
+        Ok(())
+    }
+
+    pub fn read_bit(&mut self) -> io::Result<u32> {
+        if self.count == 0 {
+            try!(self.fill());
+        }
+        self.count -= 1;
+        Ok((self.bits >> self.count) & 1)
+    }
+
+    pub fn read_bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            try!(self.fill());
+        }
+        self.count -= n;
+        Ok((self.bits >> self.count) & ((1 << n) - 1))
+    }
+
+    // reads an n bit magnitude category value and sign extends it as in
+    // procedure F.2.2.1
+    pub fn receive_extend(&mut self, n: u32) -> io::Result<i32> {
+        if n == 0 {
+            return Ok(0);
+        }
+        let v = try!(self.read_bits(n)) as i32;
+        if v < (1 << (n - 1)) {
+            Ok(v - (1 << n) + 1)
+        } else {
+            Ok(v)
+        }
+    }
+}
@@ -0,0 +1,132 @@
+// a CPU version of the integer iDCT done by the idct8x8_pass*.fs.glsl shaders.
+// it follows them operation for operation, including the 16-bit storage of
+// the intermediate textures, so its output matches the GPU pixel for pixel.
+
+// we want to truncate toward zero, but normal rshift truncates to -inf
+fn unbiased_rshift1(a: i32) -> i32 {
+    (a - (a >> 31)) >> 1
+}
+
+// how far the coefficients are shifted up for headroom in the transform and
+// back down after it. 12-bit coefficients are 16 times larger than 8-bit ones
+// and already fill the 16 bits of the intermediate textures, so they get none.
+// that costs them precision, since the first pass then rounds away every
+// fractional bit: 12-bit samples can be up to 5 levels from the exact transform
+// where 8-bit ones are within 1. giving them headroom would take 32-bit
+// intermediate textures and 64-bit products in idct8, whose constants are up to
+// 15 bits.
+pub fn headroom(precision: u8) -> u32 {
+    assert!(precision == 8 || precision == 12, "unsupported sample precision");
+    12 - precision as u32
+}
+
+// 1D iDCT takes a row and outputs a column
+pub fn idct8(x: &mut [i32; 8], y: &[i32; 8]) {
+    let mut t0 = y[0];
+    let mut t1 = y[1];
+    let mut t2 = y[2];
+    let mut t3 = y[3];
+    let mut t4 = y[4];
+    let mut t5 = y[5];
+    let mut t6 = y[6];
+    let mut t7 = y[7];
+    t5 -= (t3 * 2485 + 4096) >> 13;
+    t3 += (t5 * 18205 + 16384) >> 15;
+    t5 -= (t3 * 2485 + 4096) >> 13;
+    t7 -= (t1 * 3227 + 16384) >> 15;
+    t1 += (t7 * 6393 + 16384) >> 15;
+    t7 -= (t1 * 3227 + 16384) >> 15;
+    t1 += t3;
+    let t1h = unbiased_rshift1(t1);
+    t3 = t1h - t3;
+    t5 += t7;
+    t7 = unbiased_rshift1(t5) - t7;
+    t3 += (t5 * 7489 + 4096) >> 13;
+    t5 -= (t3 * 11585 + 8192) >> 14;
+    t3 -= (t5 * 19195 + 16384) >> 15;
+    t6 += (t2 * 21895 + 16384) >> 15;
+    t2 -= (t6 * 15137 + 8192) >> 14;
+    t6 += (t2 * 21895 + 16384) >> 15;
+    t0 += (t4 * 13573 + 16384) >> 15;
+    t4 -= (t0 * 11585 + 8192) >> 14;
+    t0 += (t4 * 13573 + 16384) >> 15;
+    t4 = t2 - t4;
+    let t4h = unbiased_rshift1(t4);
+    t2 = t4h - t2;
+    t6 = t0 - t6;
+    let t6h = unbiased_rshift1(t6);
+    t0 -= t6h;
+    t7 = t6h - t7;
+    t6 -= t7;
+    t2 += unbiased_rshift1(t3);
+    t3 = t2 - t3;
+    t5 += t4h;
+    t4 -= t5;
+    t0 += t1h;
+    t1 = t0 - t1;
+    x[0] = t0;
+    x[1] = t4;
+    x[2] = t2;
+    x[3] = t6;
+    x[4] = t7;
+    x[5] = t3;
+    x[6] = t5;
+    x[7] = t1;
+}
+
+// transforms the 8x8 block of coefficients at offset in data, which has rows
+// stride values apart, and writes the samples to the same place in out
+pub fn idct8x8(data: &[i16], out: &mut [i16], offset: usize, stride: usize, precision: u8) {
+    let shift = headroom(precision);
+    let mut x = [0; 8];
+    let mut y = [0; 8];
+
+    // pass 1 transforms each row, and the results are stored as 16-bit
+    // values in the intermediate textures
+    let mut rows = [[0i16; 8]; 8];
+    for j in 0..8 {
+        for i in 0..8 {
+            // we have to shift up for headroom in the transform
+            y[i] = (data[offset + j * stride + i] as i32) << shift;
+        }
+        idct8(&mut x, &y);
+        for i in 0..8 {
+            rows[j][i] = x[i] as i16;
+        }
+    }
+
+    // pass 2 transforms each column of the first pass output
+    let mut cols = [[0i16; 8]; 8];
+    for i in 0..8 {
+        for j in 0..8 {
+            y[j] = rows[j][i] as i32;
+        }
+        idct8(&mut x, &y);
+        for j in 0..8 {
+            cols[i][j] = x[j] as i16;
+        }
+    }
+
+    // pass 3 puts the pixels back in place and shifts back down post transform
+    for j in 0..8 {
+        for i in 0..8 {
+            out[offset + j * stride + i] = ((cols[i][j] as i32) >> shift) as i16;
+        }
+    }
+}
+
+// the CPU equivalent of decode_atlas for one plane, turning a plane of
+// coefficients laid out as pack_coeffs expects into samples of the given
+// precision (without the level shift)
+pub fn idct_plane(width: u32, height: u32, data: &[i16], precision: u8) -> Vec<i16> {
+    assert!(width % 8 == 0 && height % 8 == 0);
+    assert!(data.len() == (width * height) as usize);
+    let stride = width as usize;
+    let mut out = vec![0; data.len()];
+    for by in 0..(height >> 3) as usize {
+        for bx in 0..(width >> 3) as usize {
+            idct8x8(data, &mut out, (by << 3) * stride + (bx << 3), stride, precision);
+        }
+    }
+    out
+}
@@ -0,0 +1,208 @@
+#version 430
+
+// one workgroup decodes one 8x8 block, doing what the unpack and three iDCT
+// passes do but keeping everything in shared memory
+layout(local_size_x = 8, local_size_y = 8) in;
+
+// we want to truncate toward zero, but normal rshift truncates to -inf
+#define UNBIASED_RSHIFT1(a) (((a) - ((a) >> 31)) >> 1)
+
+// 1D iDCT takes a row and outputs a column
+void idct8(out int x[8], const int y[8]) {
+  int t0;
+  int t1;
+  int t1h;
+  int t2;
+  int t3;
+  int t4;
+  int t4h;
+  int t5;
+  int t6;
+  int t6h;
+  int t7;
+  t0 = y[0];
+  t1 = y[1];
+  t2 = y[2];
+  t3 = y[3];
+  t4 = y[4];
+  t5 = y[5];
+  t6 = y[6];
+  t7 = y[7];
+  t5 -= (t3*2485 + 4096) >> 13;
+  t3 += (t5*18205 + 16384) >> 15;
+  t5 -= (t3*2485 + 4096) >> 13;
+  t7 -= (t1*3227 + 16384) >> 15;
+  t1 += (t7*6393 + 16384) >> 15;
+  t7 -= (t1*3227 + 16384) >> 15;
+  t1 += t3;
+  t1h = UNBIASED_RSHIFT1(t1);
+  t3 = t1h - t3;
+  t5 += t7;
+  t7 = UNBIASED_RSHIFT1(t5) - t7;
+  t3 += (t5*7489 + 4096) >> 13;
+  t5 -= (t3*11585 + 8192) >> 14;
+  t3 -= (t5*19195 + 16384) >> 15;
+  t6 += (t2*21895 + 16384) >> 15;
+  t2 -= (t6*15137 + 8192) >> 14;
+  t6 += (t2*21895 + 16384) >> 15;
+  t0 += (t4*13573 + 16384) >> 15;
+  t4 -= (t0*11585 + 8192) >> 14;
+  t0 += (t4*13573 + 16384) >> 15;
+  t4 = t2 - t4;
+  t4h = UNBIASED_RSHIFT1(t4);
+  t2 = t4h - t2;
+  t6 = t0 - t6;
+  t6h = UNBIASED_RSHIFT1(t6);
+  t0 -= t6h;
+  t7 = t6h - t7;
+  t6 -= t7;
+  t2 += UNBIASED_RSHIFT1(t3);
+  t3 = t2 - t3;
+  t5 += t4h;
+  t4 -= t5;
+  t0 += t1h;
+  t1 = t0 - t1;
+  x[0] = t0;
+  x[1] = t4;
+  x[2] = t2;
+  x[3] = t6;
+  x[4] = t7;
+  x[5] = t3;
+  x[6] = t5;
+  x[7] = t1;
+}
+
+uniform ivec2 plane_dims;
+uniform usampler2D index_texture;
+uniform usampler2D quant_table;
+uniform usamplerBuffer packed_coeffs;
+
+// the first block row of each plane in the atlas, unused planes being past
+// the end of it
+uniform ivec4 plane_rows;
+
+// how far the coefficients are shifted up for headroom in the transform,
+// which depends on the sample precision
+uniform int headroom;
+
+// the planes' quantization tables are stacked in the same order as the planes,
+// so this finds the first row of the table for a block row
+int quant_row(int block_row) {
+  int plane = int(block_row >= plane_rows.y) + int(block_row >= plane_rows.z) +
+    int(block_row >= plane_rows.w);
+  return plane * 8;
+}
+
+layout(std430) buffer Samples {
+  int samples[];
+};
+
+// the natural order position of each zigzag position
+const int NATURAL[64] = int[64](
+   0,  1,  8, 16,  9,  2,  3, 10,
+  17, 24, 32, 25, 18, 11,  4,  5,
+  12, 19, 26, 33, 40, 48, 41, 34,
+  27, 20, 13,  6,  7, 14, 21, 28,
+  35, 42, 49, 56, 57, 50, 43, 36,
+  29, 22, 15, 23, 30, 37, 44, 51,
+  58, 59, 52, 45, 38, 31, 39, 46,
+  53, 60, 61, 54, 47, 55, 62, 63
+);
+
+shared int coeffs[64];
+shared int rows[64];
+shared int cols[64];
+
+// the fragment path stores intermediate results in 16-bit textures, so we
+// truncate to 16 bits at the same points to match it
+int wrap16(int a) {
+  return (a << 16) >> 16;
+}
+
+void main() {
+  int i;
+  int x[8], y[8];
+  ivec2 block = ivec2(gl_WorkGroupID.xy);
+  ivec2 offset = ivec2(gl_LocalInvocationID.xy);
+  int table_row = quant_row(block.y);
+
+  coeffs[offset.y * 8 + offset.x] = 0;
+  barrier();
+
+  // a single invocation walks the block's coefficients and scatters them
+  if (offset.x == 0 && offset.y == 0) {
+    int index = int(texelFetch(index_texture, block, 0).r);
+    int pos = -1;
+    while (pos < 63) {
+      uint packed_coeff = texelFetch(packed_coeffs, index).r;
+      index += 1;
+
+      if (packed_coeff == uint(0)) {
+        // all the rest of the coefficients are zero
+        break;
+      }
+
+      int zeros = int((packed_coeff >> 12) & uint(0x000f));
+      int coeff;
+      if ((packed_coeff & uint(0x0fff)) == uint(0x800)) {
+        // escaped coefficients are stored whole in the next word
+        uint escaped = texelFetch(packed_coeffs, index).r;
+        index += 1;
+        coeff = int(escaped);
+        if ((escaped & uint(0x8000)) == uint(0x8000)) {
+          coeff -= 0x10000;
+        }
+      } else if ((packed_coeff & uint(0x800)) == uint(0x800)) {
+        coeff = int(uint(~0x0fff) | (packed_coeff & uint(0x0fff)));
+      } else {
+        coeff = int(packed_coeff & uint(0x0fff));
+      }
+
+      pos += zeros + 1;
+      if (pos > 63) {
+        break;
+      }
+
+      int n = NATURAL[pos];
+      if (coeff != 0) {
+        // dequantize, saturating as the unpack shader does
+        int q = int(texelFetch(quant_table, ivec2(n & 7, table_row + (n >> 3)), 0).r);
+        coeffs[n] = clamp(coeff * q, -32768, 32767);
+      }
+    }
+  }
+  memoryBarrierShared();
+  barrier();
+
+  // the first column of invocations transforms the rows
+  if (offset.x == 0) {
+    for (i = 0; i < 8; i++) {
+      // we have to shift up for headroom in the transform
+      y[i] = coeffs[offset.y * 8 + i] << headroom;
+    }
+    idct8(x, y);
+    for (i = 0; i < 8; i++) {
+      rows[offset.y * 8 + i] = wrap16(x[i]);
+    }
+  }
+  memoryBarrierShared();
+  barrier();
+
+  // and then the columns
+  if (offset.x == 0) {
+    for (i = 0; i < 8; i++) {
+      y[i] = rows[i * 8 + offset.y];
+    }
+    idct8(x, y);
+    for (i = 0; i < 8; i++) {
+      cols[i * 8 + offset.y] = wrap16(x[i]);
+    }
+  }
+  memoryBarrierShared();
+  barrier();
+
+  // every invocation writes out its own pixel, shifting back down post
+  // transform
+  ivec2 pixel = (block << 3) + offset;
+  samples[pixel.y * plane_dims.x + pixel.x] = cols[offset.y * 8 + offset.x] >> headroom;
+}
@@ -76,6 +76,10 @@ out ivec4 pass1_bot;
 uniform ivec2 plane_dims;
 uniform isampler2D data;
 
+// how far the coefficients are shifted up for headroom in the transform,
+// which depends on the sample precision
+uniform int headroom;
+
 void main() {
   int i;
   int x[8], y[8];
@@ -87,7 +91,7 @@ void main() {
   // fetch our row of texels in our block
   for (i = 0; i < 8; i++) {
     // we have to shift up for headroom in the transform
-    y[i] = texelFetch(data, ivec2((block.x << 3) + i, i_tex_coords.y), 0).r << 4;
+    y[i] = texelFetch(data, ivec2((block.x << 3) + i, i_tex_coords.y), 0).r << headroom;
   }
 
   // transform
@@ -7,6 +7,8 @@ out int color;
 uniform ivec2 plane_dims;
 uniform isampler2D pass2_top;
 uniform isampler2D pass2_bot;
+// the shift up the first pass did
+uniform int headroom;
 
 void main() {
   int c;
@@ -24,5 +26,5 @@ void main() {
   }
 
   // shift back down post transform
-  color = c >> 4;
+  color = c >> headroom;
 }
@@ -0,0 +1,48 @@
+// the decoder puts upsample.glsl in front of this, with the version line
+
+in vec2 v_tex_coords;
+
+out vec4 color;
+
+uniform ivec2 plane_dims;
+uniform ivec2 sampling;
+uniform ivec2 origin;
+uniform ivec2 covered;
+uniform vec3 weights;
+uniform vec3 color_offset;
+// the largest level a sample of the image's precision can have
+uniform float max_level;
+uniform isampler2D pass2_top;
+uniform isampler2D pass2_bot;
+// the shift up the first pass did
+uniform int headroom;
+
+// does the last pass for a sample of the plane at origin, clamping to its
+// edges
+int fetch(ivec2 origin, ivec2 covered, ivec2 p) {
+  int c;
+
+  // find the sample in the atlas, then its block and offset
+  ivec2 i_tex_coords = origin + clamp(p, ivec2(0), covered - 1);
+  ivec2 block = i_tex_coords >> 3;
+  ivec2 offset = i_tex_coords % 8;
+
+  // unpack the pixel value
+  if (offset.y < 4) {
+    c = texelFetch(pass2_top, ivec2(block.x, (block.y << 3) + offset.x), 0)[offset.y];
+  } else {
+    c = texelFetch(pass2_bot, ivec2(block.x, (block.y << 3) + offset.x), 0)[offset.y - 4];
+  }
+
+  // shift back down post transform
+  return c >> headroom;
+}
+
+// outputs one plane's share of each color channel for every pixel of the
+// image, which additive blending sums with the other planes' shares
+void main() {
+  ivec2 tex_coords = ivec2(v_tex_coords * plane_dims);
+  float value = upsample(tex_coords, origin, covered, sampling);
+
+  color = vec4((weights * value + color_offset) / max_level, 0.0);
+}
@@ -0,0 +1,883 @@
+use arithmetic::ArithmeticDecoder;
+use huffman::{BitReader, HuffmanTable};
+use make_zigzag_table;
+use container::ColorModel;
+use markers::{self, AdobeSegment, Error, FrameHeader, FrameType, ScanHeader, SegmentType};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+
+// quantized coefficients for one component, laid out in 8x8 blocks the same
+// way pack_coeffs expects them. the dimensions cover every block in the MCU
+// grid, so they may be larger than the component itself. quant_table is in
+// natural order, and planes that are already dequantized have a table of ones.
+pub struct CoeffPlane {
+    pub id: u8,
+    pub h: u8,
+    pub v: u8,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<i16>,
+    pub quant_table: Vec<u16>,
+}
+
+// where the entropy coded data of a restart interval begins, as a byte offset
+// from the start of the input. every interval can be decoded on its own
+// starting from here since the DC predictions are reset at each one.
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPosition {
+    pub scan: usize,
+    pub mcu: u32,
+    pub offset: usize,
+}
+
+pub struct Coefficients {
+    pub width: u32,
+    pub height: u32,
+    pub planes: Vec<CoeffPlane>,
+    pub restart_interval: u16,
+    pub restarts: Vec<RestartPosition>,
+    pub adobe: Option<AdobeSegment>,
+    // bits per sample, which is 8 or 12
+    pub precision: u8,
+}
+
+impl Coefficients {
+    // works out what the components are from their number and the Adobe
+    // segment, if there is one. Adobe's CMYK is always inverted.
+    pub fn color_model(&self) -> Result<ColorModel, Error> {
+        match (self.planes.len(), self.adobe.map(|a| a.transform)) {
+            (1, _) => Ok(ColorModel::Gray),
+            (3, None) | (3, Some(1)) => Ok(ColorModel::YCbCr),
+            (3, Some(_)) => Err(Error::Unsupported("RGB components")),
+            (4, None) => Ok(ColorModel::Cmyk),
+            (4, Some(0)) => Ok(ColorModel::InvertedCmyk),
+            (4, Some(2)) => Ok(ColorModel::Ycck),
+            (4, Some(_)) => Err(Error::Malformed("Adobe transform")),
+            _ => Err(Error::Unsupported("number of components")),
+        }
+    }
+}
+
+// keeps track of how far into the input we are so restart positions can be
+// reported as offsets
+struct CountingInput<'a> {
+    input: &'a mut Iterator<Item=io::Result<u8>>,
+    offset: usize,
+}
+
+impl<'a> Iterator for CountingInput<'a> {
+    type Item = io::Result<u8>;
+
+    fn next(&mut self) -> Option<io::Result<u8>> {
+        let b = self.input.next();
+        if b.is_some() {
+            self.offset += 1;
+        }
+        b
+    }
+}
+
+// how a scan refines the coefficients, following G.1.1.1 for progressive
+// frames. sequential scans code every coefficient of a block at once.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ScanKind {
+    Sequential,
+    DcFirst,
+    DcRefine,
+    AcFirst,
+    AcRefine,
+}
+
+// per component state while decoding a huffman coded scan
+struct ScanComponentState<'a> {
+    dc: Option<&'a HuffmanTable>,
+    ac: Option<&'a HuffmanTable>,
+    pred: i32,
+}
+
+// the largest magnitude category, which 12-bit DC differences need. anything
+// larger is corrupt and would overflow the coefficients.
+const MAX_CATEGORY: u32 = 15;
+
+fn receive_extend(reader: &mut BitReader, s: u32) -> Result<i32, Error> {
+    if s > MAX_CATEGORY {
+        return Err(Error::Malformed("magnitude category"));
+    }
+    Ok(try!(reader.receive_extend(s)))
+}
+
+fn decode_block(reader: &mut BitReader, dc: &HuffmanTable, ac: &HuffmanTable,
+                pred: &mut i32, zigzag: &Vec<Vec<usize>>, block: &mut [i16],
+                block_offset: usize) -> Result<(), Error> {
+    let t = try!(dc.decode(reader)) as u32;
+    *pred += try!(receive_extend(reader, t));
+    block[block_offset] = *pred as i16;
+
+    let mut k = 1;
+    while k < 64 {
+        let rs = try!(ac.decode(reader));
+        let r = (rs >> 4) as usize;
+        let s = (rs & 0x0f) as u32;
+        if s == 0 {
+            if r == 15 {
+                k += 16;
+                continue;
+            }
+            // end of block
+            break;
+        }
+        k += r;
+        if k > 63 {
+            return Err(Error::Malformed("coefficient run past end of block"));
+        }
+        block[block_offset + zigzag[k >> 3][k & 7]] = try!(receive_extend(reader, s)) as i16;
+        k += 1;
+    }
+    Ok(())
+}
+
+fn decode_dc_first(reader: &mut BitReader, dc: &HuffmanTable, pred: &mut i32, al: u8,
+                   block: &mut [i16], block_offset: usize) -> Result<(), Error> {
+    let t = try!(dc.decode(reader)) as u32;
+    *pred += try!(receive_extend(reader, t));
+    block[block_offset] = (*pred << al) as i16;
+    Ok(())
+}
+
+fn decode_dc_refine(reader: &mut BitReader, al: u8, block: &mut [i16],
+                    block_offset: usize) -> Result<(), Error> {
+    if try!(reader.read_bit()) == 1 {
+        block[block_offset] |= 1 << al;
+    }
+    Ok(())
+}
+
+// reads the length of a run of blocks with no further coefficients in this
+// band, less the current one
+fn read_eob_run(reader: &mut BitReader, r: u32) -> Result<u32, Error> {
+    let mut eob_run = (1 << r) - 1;
+    if r > 0 {
+        eob_run += try!(reader.read_bits(r));
+    }
+    Ok(eob_run)
+}
+
+fn decode_ac_first(reader: &mut BitReader, ac: &HuffmanTable, scan: &ScanHeader,
+                   eob_run: &mut u32, zigzag: &Vec<Vec<usize>>, block: &mut [i16],
+                   block_offset: usize) -> Result<(), Error> {
+    if *eob_run > 0 {
+        *eob_run -= 1;
+        return Ok(());
+    }
+    let mut k = scan.ss as usize;
+    while k <= scan.se as usize {
+        let rs = try!(ac.decode(reader));
+        let r = (rs >> 4) as u32;
+        let s = (rs & 0x0f) as u32;
+        if s == 0 {
+            if r == 15 {
+                k += 16;
+                continue;
+            }
+            *eob_run = try!(read_eob_run(reader, r));
+            break;
+        }
+        k += r as usize;
+        if k > scan.se as usize {
+            return Err(Error::Malformed("coefficient run past end of band"));
+        }
+        let value = try!(receive_extend(reader, s)) << scan.al;
+        block[block_offset + zigzag[k >> 3][k & 7]] = value as i16;
+        k += 1;
+    }
+    Ok(())
+}
+
+// refines an already nonzero coefficient by one bit
+fn refine_coeff(reader: &mut BitReader, coeff: &mut i16, p1: i16) -> Result<(), Error> {
+    if try!(reader.read_bit()) == 1 && (*coeff & p1) == 0 {
+        if *coeff >= 0 {
+            *coeff += p1;
+        } else {
+            *coeff -= p1;
+        }
+    }
+    Ok(())
+}
+
+// successive approximation of AC coefficients as in G.1.2.3. newly nonzero
+// coefficients are coded as a run of zero coefficients to skip, while every
+// coefficient that is already nonzero gets a correction bit along the way.
+fn decode_ac_refine(reader: &mut BitReader, ac: &HuffmanTable, scan: &ScanHeader,
+                    eob_run: &mut u32, zigzag: &Vec<Vec<usize>>, block: &mut [i16],
+                    block_offset: usize) -> Result<(), Error> {
+    let p1 = 1i16 << scan.al;
+    let se = scan.se as usize;
+    let mut k = scan.ss as usize;
+
+    if *eob_run == 0 {
+        while k <= se {
+            let rs = try!(ac.decode(reader));
+            let mut r = (rs >> 4) as i32;
+            let s = rs & 0x0f;
+            let mut value = 0;
+            if s != 0 {
+                if s != 1 {
+                    return Err(Error::Malformed("refinement coefficient magnitude"));
+                }
+                value = if try!(reader.read_bit()) == 1 { p1 } else { -p1 };
+            } else if r != 15 {
+                *eob_run = try!(read_eob_run(reader, r as u32)) + 1;
+                break;
+            }
+
+            while k <= se {
+                let coeff = &mut block[block_offset + zigzag[k >> 3][k & 7]];
+                if *coeff != 0 {
+                    try!(refine_coeff(reader, coeff, p1));
+                } else {
+                    if r == 0 {
+                        if value != 0 {
+                            *coeff = value;
+                        }
+                        k += 1;
+                        break;
+                    }
+                    r -= 1;
+                }
+                k += 1;
+            }
+        }
+    }
+
+    if *eob_run > 0 {
+        // the rest of the band only carries correction bits
+        while k <= se {
+            let coeff = &mut block[block_offset + zigzag[k >> 3][k & 7]];
+            if *coeff != 0 {
+                try!(refine_coeff(reader, coeff, p1));
+            }
+            k += 1;
+        }
+        *eob_run -= 1;
+    }
+    Ok(())
+}
+
+fn decode_block_kind(kind: ScanKind, reader: &mut BitReader, scan: &ScanHeader,
+                     component: &mut ScanComponentState, eob_run: &mut u32,
+                     zigzag: &Vec<Vec<usize>>, block: &mut [i16],
+                     block_offset: usize) -> Result<(), Error> {
+    match kind {
+        ScanKind::Sequential => {
+            decode_block(reader, component.dc.unwrap(), component.ac.unwrap(),
+                         &mut component.pred, zigzag, block, block_offset)
+        },
+        ScanKind::DcFirst => {
+            decode_dc_first(reader, component.dc.unwrap(), &mut component.pred, scan.al,
+                            block, block_offset)
+        },
+        ScanKind::DcRefine => decode_dc_refine(reader, scan.al, block, block_offset),
+        ScanKind::AcFirst => {
+            decode_ac_first(reader, component.ac.unwrap(), scan, eob_run, zigzag, block,
+                            block_offset)
+        },
+        ScanKind::AcRefine => {
+            decode_ac_refine(reader, component.ac.unwrap(), scan, eob_run, zigzag, block,
+                             block_offset)
+        },
+    }
+}
+
+// what decoding a scan needs from its entropy decoder, so that huffman and
+// arithmetic coded scans share the walk over MCUs and restart intervals
+trait EntropyDecoder {
+    // decodes the next block of the scan component at index c
+    fn decode_block(&mut self, c: usize, zigzag: &Vec<Vec<usize>>, block: &mut [i16],
+                    block_offset: usize) -> Result<(), Error>;
+
+    // consumes restart marker n and resets everything for the next interval
+    fn restart(&mut self, n: u8) -> Result<(), Error>;
+
+    // the number of bytes of entropy coded data consumed so far
+    fn position(&self) -> usize;
+
+    // the marker which terminated the entropy coded data, if there was one
+    fn marker(&self) -> Option<u8>;
+}
+
+struct HuffmanScan<'a, 'b> {
+    reader: BitReader<'a>,
+    kind: ScanKind,
+    scan: &'b ScanHeader,
+    components: Vec<ScanComponentState<'b>>,
+    eob_run: u32,
+}
+
+impl<'a, 'b> EntropyDecoder for HuffmanScan<'a, 'b> {
+    fn decode_block(&mut self, c: usize, zigzag: &Vec<Vec<usize>>, block: &mut [i16],
+                    block_offset: usize) -> Result<(), Error> {
+        decode_block_kind(self.kind, &mut self.reader, self.scan, &mut self.components[c],
+                          &mut self.eob_run, zigzag, block, block_offset)
+    }
+
+    fn restart(&mut self, n: u8) -> Result<(), Error> {
+        try!(self.reader.read_restart(n));
+        for c in self.components.iter_mut() {
+            c.pred = 0;
+        }
+        self.eob_run = 0;
+        Ok(())
+    }
+
+    fn position(&self) -> usize {
+        self.reader.position()
+    }
+
+    fn marker(&self) -> Option<u8> {
+        self.reader.marker()
+    }
+}
+
+// decodes the bits of a magnitude below its top bit m, which all share the bin
+// at st, and returns the value they code
+fn decode_magnitude(decoder: &mut ArithmeticDecoder, stats: &mut [u8], st: usize, m: i32)
+                    -> Result<i32, Error> {
+    let mut v = m;
+    let mut bit = m >> 1;
+    while bit != 0 {
+        if try!(decoder.decode(&mut stats[st])) == 1 {
+            v |= bit;
+        }
+        bit >>= 1;
+    }
+    Ok(v + 1)
+}
+
+// decodes a DC difference as in F.2.4.1, and works out the conditioning of the
+// next one from it as in F.1.4.4.1.2. l and u are the DC conditioning bounds.
+fn decode_arithmetic_dc(decoder: &mut ArithmeticDecoder, stats: &mut [u8; 64],
+                        context: &mut usize, (l, u): (u8, u8)) -> Result<i32, Error> {
+    let mut st = *context;
+    if try!(decoder.decode(&mut stats[st])) == 0 {
+        *context = 0;
+        return Ok(0);
+    }
+    let sign = try!(decoder.decode(&mut stats[st + 1])) as usize;
+    st += 2 + sign;
+    let mut m = try!(decoder.decode(&mut stats[st])) as i32;
+    if m != 0 {
+        st = 20;
+        while try!(decoder.decode(&mut stats[st])) == 1 {
+            m <<= 1;
+            if m == 0x8000 {
+                return Err(Error::Malformed("arithmetic coded magnitude"));
+            }
+            st += 1;
+        }
+    }
+    *context = if m < (1 << l) >> 1 {
+        0
+    } else if m > (1 << u) >> 1 {
+        12 + sign * 4
+    } else {
+        4 + sign * 4
+    };
+    let v = try!(decode_magnitude(decoder, stats, st + 14, m));
+    Ok(if sign == 1 { -v } else { v })
+}
+
+// decodes the AC coefficients from ss to se as in F.2.4.2, which the first
+// scan of a progressive band does too, shifting them up by al. kx is the AC
+// conditioning.
+fn decode_arithmetic_ac(decoder: &mut ArithmeticDecoder, stats: &mut [u8; 256], kx: u8,
+                        ss: usize, se: usize, al: u8, zigzag: &Vec<Vec<usize>>,
+                        block: &mut [i16], block_offset: usize) -> Result<(), Error> {
+    let mut k = ss;
+    while k <= se {
+        let mut st = 3 * (k - 1);
+        if try!(decoder.decode(&mut stats[st])) == 1 {
+            // end of block
+            break;
+        }
+        while try!(decoder.decode(&mut stats[st + 1])) == 0 {
+            st += 3;
+            k += 1;
+            if k > se {
+                return Err(Error::Malformed("coefficient run past end of band"));
+            }
+        }
+        let sign = try!(decoder.decode_fixed());
+        st += 2;
+        let mut m = try!(decoder.decode(&mut stats[st])) as i32;
+        if m != 0 && try!(decoder.decode(&mut stats[st])) == 1 {
+            m <<= 1;
+            st = if k <= kx as usize { 189 } else { 217 };
+            while try!(decoder.decode(&mut stats[st])) == 1 {
+                m <<= 1;
+                if m == 0x8000 {
+                    return Err(Error::Malformed("arithmetic coded magnitude"));
+                }
+                st += 1;
+            }
+        }
+        let v = try!(decode_magnitude(decoder, stats, st + 14, m));
+        let v = if sign == 1 { -v } else { v };
+        block[block_offset + zigzag[k >> 3][k & 7]] = (v << al) as i16;
+        k += 1;
+    }
+    Ok(())
+}
+
+// successive approximation of AC coefficients as in G.2. coefficients past
+// the end of block of the earlier scans may end the block, while every
+// coefficient that is already nonzero gets a correction bit.
+fn decode_arithmetic_ac_refine(decoder: &mut ArithmeticDecoder, stats: &mut [u8; 256],
+                               scan: &ScanHeader, zigzag: &Vec<Vec<usize>>,
+                               block: &mut [i16], block_offset: usize) -> Result<(), Error> {
+    let p1 = 1i16 << scan.al;
+    let m1 = -1i16 << scan.al;
+    let (ss, se) = (scan.ss as usize, scan.se as usize);
+    let index = |k: usize| block_offset + zigzag[k >> 3][k & 7];
+    let mut eob = se;
+    while eob > 0 && block[index(eob)] == 0 {
+        eob -= 1;
+    }
+
+    let mut k = ss;
+    while k <= se {
+        let mut st = 3 * (k - 1);
+        if k > eob && try!(decoder.decode(&mut stats[st])) == 1 {
+            break;
+        }
+        loop {
+            let coeff = &mut block[index(k)];
+            if *coeff != 0 {
+                if try!(decoder.decode(&mut stats[st + 2])) == 1 {
+                    *coeff += if *coeff < 0 { m1 } else { p1 };
+                }
+                break;
+            }
+            if try!(decoder.decode(&mut stats[st + 1])) == 1 {
+                *coeff = if try!(decoder.decode_fixed()) == 1 { m1 } else { p1 };
+                break;
+            }
+            st += 3;
+            k += 1;
+            if k > se {
+                return Err(Error::Malformed("coefficient run past end of band"));
+            }
+        }
+        k += 1;
+    }
+    Ok(())
+}
+
+// per component state while decoding an arithmetic coded scan
+struct ArithmeticComponentState {
+    dc_table: usize,
+    ac_table: usize,
+    // the conditioning category of the previous DC difference
+    dc_context: usize,
+    pred: i32,
+}
+
+struct ArithmeticScan<'a, 'b> {
+    decoder: ArithmeticDecoder<'a>,
+    kind: ScanKind,
+    scan: &'b ScanHeader,
+    components: Vec<ArithmeticComponentState>,
+    // the statistics bins of each table, which adapt over a restart interval
+    dc_stats: [[u8; 64]; 4],
+    ac_stats: [[u8; 256]; 4],
+    dc_conditioning: [(u8, u8); 4],
+    ac_conditioning: [u8; 4],
+}
+
+impl<'a, 'b> EntropyDecoder for ArithmeticScan<'a, 'b> {
+    fn decode_block(&mut self, c: usize, zigzag: &Vec<Vec<usize>>, block: &mut [i16],
+                    block_offset: usize) -> Result<(), Error> {
+        let component = &mut self.components[c];
+        let decoder = &mut self.decoder;
+        let dc_stats = &mut self.dc_stats[component.dc_table];
+        let ac_stats = &mut self.ac_stats[component.ac_table];
+        let dc_conditioning = self.dc_conditioning[component.dc_table];
+        let kx = self.ac_conditioning[component.ac_table];
+        let scan = self.scan;
+        match self.kind {
+            ScanKind::Sequential => {
+                component.pred += try!(decode_arithmetic_dc(decoder, dc_stats,
+                                                            &mut component.dc_context,
+                                                            dc_conditioning));
+                block[block_offset] = component.pred as i16;
+                decode_arithmetic_ac(decoder, ac_stats, kx, 1, 63, 0, zigzag, block,
+                                     block_offset)
+            },
+            ScanKind::DcFirst => {
+                component.pred += try!(decode_arithmetic_dc(decoder, dc_stats,
+                                                            &mut component.dc_context,
+                                                            dc_conditioning));
+                block[block_offset] = (component.pred << scan.al) as i16;
+                Ok(())
+            },
+            ScanKind::DcRefine => {
+                if try!(decoder.decode_fixed()) == 1 {
+                    block[block_offset] |= 1 << scan.al;
+                }
+                Ok(())
+            },
+            ScanKind::AcFirst => {
+                decode_arithmetic_ac(decoder, ac_stats, kx, scan.ss as usize, scan.se as usize,
+                                     scan.al, zigzag, block, block_offset)
+            },
+            ScanKind::AcRefine => {
+                decode_arithmetic_ac_refine(decoder, ac_stats, scan, zigzag, block, block_offset)
+            },
+        }
+    }
+
+    fn restart(&mut self, n: u8) -> Result<(), Error> {
+        try!(self.decoder.read_restart(n));
+        self.dc_stats = [[0; 64]; 4];
+        self.ac_stats = [[0; 256]; 4];
+        for c in self.components.iter_mut() {
+            c.dc_context = 0;
+            c.pred = 0;
+        }
+        Ok(())
+    }
+
+    fn position(&self) -> usize {
+        self.decoder.position()
+    }
+
+    fn marker(&self) -> Option<u8> {
+        self.decoder.marker()
+    }
+}
+
+// decodes every MCU of a scan into the planes, noting where each restart
+// interval begins. indices are the frame components of the scan components.
+fn decode_mcus<D: EntropyDecoder>(decoder: &mut D, frame: &FrameHeader, indices: &[usize],
+                                  planes: &mut [CoeffPlane], restart_interval: u16,
+                                  restarts: &mut Vec<RestartPosition>, scan_index: usize,
+                                  start: usize) -> Result<(), Error> {
+    let zigzags: Vec<_> = planes.iter().map(|p| make_zigzag_table(p.width)).collect();
+
+    // a non-interleaved scan has single block MCUs covering only the
+    // component itself
+    let (mcus_x, mcus_y) = if indices.len() == 1 {
+        frame.component_blocks(&frame.components[indices[0]])
+    } else {
+        (frame.mcus_x(), frame.mcus_y())
+    };
+    let interval = restart_interval as u32;
+    let mut next_restart = 0;
+    for mcu in 0..mcus_x * mcus_y {
+        if interval > 0 && mcu % interval == 0 {
+            if mcu > 0 {
+                try!(decoder.restart(next_restart));
+                next_restart = (next_restart + 1) & 7;
+            }
+            restarts.push(RestartPosition {
+                scan: scan_index,
+                mcu: mcu,
+                offset: start + decoder.position(),
+            });
+        }
+
+        let (mx, my) = (mcu % mcus_x, mcu / mcus_x);
+        if indices.len() == 1 {
+            let plane = &mut planes[indices[0]];
+            let block_offset = ((my << 3) * plane.width + (mx << 3)) as usize;
+            try!(decoder.decode_block(0, &zigzags[indices[0]], &mut plane.data, block_offset));
+            continue;
+        }
+        for (c, &index) in indices.iter().enumerate() {
+            let fc = &frame.components[index];
+            let plane = &mut planes[index];
+            for v in 0..fc.v as u32 {
+                for h in 0..fc.h as u32 {
+                    let bx = mx * fc.h as u32 + h;
+                    let by = my * fc.v as u32 + v;
+                    let block_offset = ((by << 3) * plane.width + (bx << 3)) as usize;
+                    try!(decoder.decode_block(c, &zigzags[index], &mut plane.data,
+                                              block_offset));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+struct DecodeState {
+    frame: Option<FrameHeader>,
+    planes: Vec<CoeffPlane>,
+    quant_tables: [Option<Vec<u16>>; 4],
+    // whether each component has had its quantization table taken, which
+    // happens when its first scan starts since later segments may redefine it
+    quant_latched: Vec<bool>,
+    dc_tables: [Option<HuffmanTable>; 4],
+    ac_tables: [Option<HuffmanTable>; 4],
+    // the L and U bounds of each DC arithmetic coding table and the Kx of
+    // each AC one
+    dc_conditioning: [(u8, u8); 4],
+    ac_conditioning: [u8; 4],
+    restart_interval: u16,
+    restarts: Vec<RestartPosition>,
+    scans: usize,
+    adobe: Option<AdobeSegment>,
+}
+
+impl DecodeState {
+    fn start_frame(&mut self, frame: FrameHeader) -> Result<(), Error> {
+        if self.frame.is_some() {
+            return Err(Error::Malformed("more than one frame"));
+        }
+        // every frame type but baseline may have 12-bit samples
+        match (frame.frame_type, frame.precision) {
+            (_, 8) => {},
+            (FrameType::BaselineDct, _) => {
+                return Err(Error::Malformed("baseline sample precision"));
+            },
+            (_, 12) => {},
+            _ => return Err(Error::Unsupported("sample precision other than 8 or 12 bits")),
+        }
+        if frame.width == 0 || frame.height == 0 {
+            return Err(Error::Unsupported("frame without explicit dimensions"));
+        }
+        let (mcus_x, mcus_y) = (frame.mcus_x(), frame.mcus_y());
+        self.planes = frame.components.iter().map(|c| {
+            let width = mcus_x * c.h as u32 * 8;
+            let height = mcus_y * c.v as u32 * 8;
+            CoeffPlane {
+                id: c.id,
+                h: c.h,
+                v: c.v,
+                width: width,
+                height: height,
+                data: vec![0; (width * height) as usize],
+                quant_table: vec![1; 64],
+            }
+        }).collect();
+        self.quant_latched = vec![false; frame.components.len()];
+        self.frame = Some(frame);
+        Ok(())
+    }
+
+    fn scan_kind(frame: &FrameHeader, scan: &ScanHeader) -> Result<ScanKind, Error> {
+        if !frame.frame_type.is_progressive() {
+            if scan.ss != 0 || scan.se != 63 || scan.ah != 0 || scan.al != 0 {
+                return Err(Error::Malformed("spectral selection in a sequential scan"));
+            }
+            return Ok(ScanKind::Sequential);
+        }
+        if scan.se > 63 || scan.ss > scan.se || scan.al > 13 {
+            return Err(Error::Malformed("spectral selection or approximation"));
+        }
+        if scan.ss == 0 {
+            if scan.se != 0 {
+                return Err(Error::Malformed("progressive scan mixing DC and AC"));
+            }
+            Ok(if scan.ah == 0 { ScanKind::DcFirst } else { ScanKind::DcRefine })
+        } else {
+            if scan.components.len() != 1 {
+                return Err(Error::Malformed("interleaved progressive AC scan"));
+            }
+            Ok(if scan.ah == 0 { ScanKind::AcFirst } else { ScanKind::AcRefine })
+        }
+    }
+
+    // decodes one scan into the quantized planes and returns the header of the
+    // segment that follows it
+    fn decode_scan(&mut self, input: &mut CountingInput, scan: &ScanHeader)
+                   -> Result<(SegmentType, usize), Error> {
+        let frame = match self.frame {
+            Some(ref f) => f,
+            None => return Err(Error::Malformed("scan before frame header")),
+        };
+        let kind = try!(DecodeState::scan_kind(frame, scan));
+        let mut indices = vec![];
+        for sc in scan.components.iter() {
+            match frame.components.iter().position(|c| c.id == sc.id) {
+                Some(index) => indices.push(index),
+                None => return Err(Error::Malformed("scan component not in frame")),
+            }
+        }
+        for &index in indices.iter() {
+            if self.quant_latched[index] {
+                continue;
+            }
+            match self.quant_tables[frame.components[index].tq as usize] {
+                Some(ref table) => self.planes[index].quant_table = table.clone(),
+                None => return Err(Error::Malformed("undefined quantization table")),
+            }
+            self.quant_latched[index] = true;
+        }
+        let start = input.offset;
+
+        let marker = if frame.frame_type.is_arithmetic() {
+            let components = scan.components.iter().map(|sc| {
+                ArithmeticComponentState {
+                    dc_table: sc.td as usize,
+                    ac_table: sc.ta as usize,
+                    dc_context: 0,
+                    pred: 0,
+                }
+            }).collect();
+            let mut decoder = ArithmeticScan {
+                decoder: ArithmeticDecoder::new(input),
+                kind: kind,
+                scan: scan,
+                components: components,
+                dc_stats: [[0; 64]; 4],
+                ac_stats: [[0; 256]; 4],
+                dc_conditioning: self.dc_conditioning,
+                ac_conditioning: self.ac_conditioning,
+            };
+            try!(decode_mcus(&mut decoder, frame, &indices, &mut self.planes,
+                             self.restart_interval, &mut self.restarts, self.scans, start));
+            decoder.marker()
+        } else {
+            let needs_dc = kind == ScanKind::Sequential || kind == ScanKind::DcFirst;
+            let needs_ac = kind == ScanKind::Sequential || kind == ScanKind::AcFirst ||
+                kind == ScanKind::AcRefine;
+            let mut components = vec![];
+            for sc in scan.components.iter() {
+                let dc = self.dc_tables[sc.td as usize].as_ref();
+                let ac = self.ac_tables[sc.ta as usize].as_ref();
+                if (needs_dc && dc.is_none()) || (needs_ac && ac.is_none()) {
+                    return Err(Error::Malformed("scan uses undefined huffman table"));
+                }
+                components.push(ScanComponentState { dc: dc, ac: ac, pred: 0 });
+            }
+            let mut decoder = HuffmanScan {
+                reader: BitReader::new(input),
+                kind: kind,
+                scan: scan,
+                components: components,
+                eob_run: 0,
+            };
+            try!(decode_mcus(&mut decoder, frame, &indices, &mut self.planes,
+                             self.restart_interval, &mut self.restarts, self.scans, start));
+            decoder.marker()
+        };
+        self.scans += 1;
+
+        match marker {
+            Some(code) => markers::parse_header(input, 0xff, code),
+            None => markers::find_marker(input),
+        }
+    }
+}
+
+// entropy decodes a baseline, extended or progressive JPEG, either huffman or
+// arithmetic coded, into quantized coefficient planes, one for each frame
+// component, along with their quantization tables
+pub fn decode(input: &mut Iterator<Item=io::Result<u8>>) -> Result<Coefficients, Error> {
+    let mut input = CountingInput { input: input, offset: 0 };
+    if try!(markers::read_header(&mut input)).0 != SegmentType::StartOfImage {
+        return Err(Error::Malformed("missing start of image"));
+    }
+
+    let mut state = DecodeState {
+        frame: None,
+        planes: vec![],
+        quant_tables: [None, None, None, None],
+        quant_latched: vec![],
+        dc_tables: [None, None, None, None],
+        ac_tables: [None, None, None, None],
+        // the defaults from F.1.4.4
+        dc_conditioning: [(0, 1); 4],
+        ac_conditioning: [5; 4],
+        restart_interval: 0,
+        restarts: vec![],
+        scans: 0,
+        adobe: None,
+    };
+
+    let mut head = try!(markers::find_marker(&mut input));
+    loop {
+        match head.0 {
+            SegmentType::EndOfImage => break,
+            SegmentType::DefineQuantizationTable => {
+                for table in try!(markers::read_quantization_table(&mut input, head.1)) {
+                    state.quant_tables[table.id as usize] = Some(table.values);
+                }
+            },
+            SegmentType::DefineHuffmanTable => {
+                for table in try!(markers::read_huffman_table(&mut input, head.1)) {
+                    let decoder = HuffmanTable::new(&table.counts, &table.values);
+                    if table.class == 0 {
+                        state.dc_tables[table.id as usize] = Some(decoder);
+                    } else {
+                        state.ac_tables[table.id as usize] = Some(decoder);
+                    }
+                }
+            },
+            SegmentType::DefineArithmeticConditioning => {
+                for table in try!(markers::read_arithmetic_conditioning(&mut input, head.1)) {
+                    let id = table.id as usize;
+                    if table.class == 0 {
+                        state.dc_conditioning[id] = (table.value & 0x0f, table.value >> 4);
+                    } else {
+                        state.ac_conditioning[id] = table.value;
+                    }
+                }
+            },
+            SegmentType::DefineRestartInterval => {
+                state.restart_interval = try!(markers::read_restart_interval(&mut input, head.1));
+            },
+            SegmentType::StartOfFrame(frame_type) => {
+                let frame = try!(markers::read_frame_header(&mut input, frame_type, head.1));
+                try!(state.start_frame(frame));
+            },
+            SegmentType::StartOfScan => {
+                let scan = try!(markers::read_scan_header(&mut input, head.1));
+                head = try!(state.decode_scan(&mut input, &scan));
+                continue;
+            },
+            SegmentType::StartOfImage => return Err(Error::Malformed("nested start of image")),
+            SegmentType::Restart(_) => return Err(Error::Malformed("restart marker outside of scan")),
+            SegmentType::App(14) => {
+                let app = try!(markers::read_app_segment(&mut input, 14, head.1));
+                if let Some(adobe) = AdobeSegment::parse(&app) {
+                    state.adobe = Some(adobe);
+                }
+            },
+            SegmentType::App(_) | SegmentType::Comment => try!(markers::skip(&mut input, head.1)),
+        }
+        head = try!(markers::find_marker(&mut input));
+    }
+
+    let frame = match state.frame {
+        Some(f) => f,
+        None => return Err(Error::Malformed("no frame header")),
+    };
+    // components without any scans still need a table
+    for (i, (plane, c)) in state.planes.iter_mut().zip(frame.components.iter()).enumerate() {
+        if state.quant_latched[i] {
+            continue;
+        }
+        match state.quant_tables[c.tq as usize] {
+            Some(ref table) => plane.quant_table = table.clone(),
+            None => return Err(Error::Malformed("undefined quantization table")),
+        }
+    }
+
+    Ok(Coefficients {
+        width: frame.width as u32,
+        height: frame.height as u32,
+        planes: state.planes,
+        restart_interval: state.restart_interval,
+        restarts: state.restarts,
+        adobe: state.adobe,
+        precision: frame.precision,
+    })
+}
+
+pub fn decode_file(path: &str) -> Result<Coefficients, Error> {
+    let f = try!(File::open(path));
+    let mut input = BufReader::new(f).bytes();
+    decode(&mut input)
+}
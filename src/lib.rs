@@ -1,20 +1,101 @@
+#[macro_use]
+extern crate glium;
+
+mod arithmetic;
+pub mod atlas;
+mod binio;
+pub mod color;
+pub mod container;
+pub mod decoder;
+mod huffman;
+pub mod idct;
+pub mod jpeg;
+pub mod markers;
+pub mod planefile;
+
+use markers::Error;
 use std::fs::File;
 use std::io::Read;
 
+// width and height cover whole blocks padded out to the MCU grid, so they may
+// be larger than the part of the image the plane covers
 pub struct Plane {
     pub width: u32,
     pub height: u32,
+    // horizontal and vertical sampling factors from the frame header
+    pub h: u8,
+    pub v: u8,
     pub packed_coeffs: Vec<u16>,
     pub packed_indices: Vec<u32>,
+    // the quantization table in natural order, which the unpack shader
+    // multiplies the packed levels by
+    pub quant_table: Vec<u16>,
 }
 
-pub fn read_data(file: &str) -> Vec<i16> {
-        let mut f = File::open(file).unwrap();
-        let mut s = String::new();
-        f.read_to_string(&mut s).unwrap();
-        s.split_whitespace()
-            .map(|coeff_str| i16::from_str_radix(coeff_str, 10).unwrap())
-            .collect()
+impl Plane {
+    // packs a plane of quantized coefficients laid out as pack_coeffs expects.
+    // coefficients that are already dequantized use a table of ones.
+    pub fn new(width: u32, height: u32, h: u8, v: u8, data: &[i16], quant_table: &[u16]) -> Plane {
+        assert!(quant_table.len() == 64);
+        let (packed_coeffs, packed_indices) = pack_coeffs(width, height, data);
+        Plane {
+            width: width,
+            height: height,
+            h: h,
+            v: v,
+            packed_coeffs: packed_coeffs,
+            packed_indices: packed_indices,
+            quant_table: quant_table.to_vec(),
+        }
+    }
+}
+
+// reads a text dump of whitespace separated coefficients
+pub fn read_data(file: &str) -> Result<Vec<i16>, Error> {
+    let mut f = try!(File::open(file));
+    let mut s = String::new();
+    try!(f.read_to_string(&mut s));
+    let mut data = vec![];
+    for coeff_str in s.split_whitespace() {
+        match i16::from_str_radix(coeff_str, 10) {
+            Ok(coeff) => data.push(coeff),
+            Err(_) => return Err(Error::Malformed("coefficient in text dump")),
+        }
+    }
+    Ok(data)
+}
+
+// parses dimensions given as WxH, which is how the tools take image sizes and
+// sampling factors
+pub fn parse_dims(s: &str) -> Option<(u32, u32)> {
+    let parts: Vec<&str> = s.split('x').collect();
+    if parts.len() != 2 {
+        return None;
+    }
+    match (parts[0].parse(), parts[1].parse()) {
+        (Ok(a), Ok(b)) => Some((a, b)),
+        _ => None,
+    }
+}
+
+// parses the HxV sampling factors of each of n planes, defaulting to 4:2:0 for
+// three planes since that is what most encoders produce
+pub fn parse_sampling<'a, I>(values: Option<I>, n: usize) -> Result<Vec<(u32, u32)>, String>
+    where I: Iterator<Item=&'a str> {
+    match values {
+        Some(values) => {
+            let mut sampling = vec![];
+            for s in values {
+                match parse_dims(s) {
+                    Some((h, v)) if h >= 1 && h <= 4 && v >= 1 && v <= 4 => sampling.push((h, v)),
+                    _ => return Err(format!("bad sampling factors {}", s)),
+                }
+            }
+            Ok(sampling)
+        },
+        None if n == 3 => Ok(vec![(2, 2), (1, 1), (1, 1)]),
+        None => Ok(vec![(1, 1); n]),
+    }
 }
 
 pub fn make_zigzag_table(stride: u32) -> Vec<Vec<usize>> {
@@ -61,12 +142,35 @@ pub fn make_dezigzag_table(stride: u32) -> Vec<usize> {
     table
 }
 
+// the packed coefficient stream is padded out to a multiple of this many values
+pub const PACK_ALIGNMENT: usize = 512;
+
+// a packed value of ESCAPE means the coefficient didn't fit in 12 bits and is
+// stored whole in the following word instead
+pub const ESCAPE: u16 = 0x0800;
+
 fn pack(zeros: u16, value: i16) -> u16 {
     (zeros << 12) | ((value as u16) & 0x0fff)
 }
 
-// returns a vec of packed coefficients and a vec of block indices
+// packs a coefficient preceded by a run of zeros, escaping it if it doesn't
+// fit. -2048 would be read as the escape so it is escaped too.
+fn push_coeff(packed: &mut Vec<u16>, zeros: u16, value: i16) {
+    if value > -2048 && value < 2048 {
+        packed.push(pack(zeros, value));
+    } else {
+        packed.push((zeros << 12) | ESCAPE);
+        packed.push(value as u16);
+    }
+}
+
+// returns a vec of packed coefficients and a vec of block indices. the plane
+// must be made of whole blocks; partial blocks at the edges of an image are
+// expected to already be padded out.
 pub fn pack_coeffs(width: u32, height: u32, data: &[i16]) -> (Vec<u16>, Vec<u32>) {
+    assert!(width % 8 == 0 && height % 8 == 0,
+            "plane dimensions {}x{} are not whole blocks", width, height);
+    assert!(data.len() == (width * height) as usize);
     let block_width = (width >> 3) as usize;
     let block_height = (height >> 3) as usize;
     let zigzag = make_zigzag_table(width);
@@ -98,7 +202,7 @@ pub fn pack_coeffs(width: u32, height: u32, data: &[i16]) -> (Vec<u16>, Vec<u32>
                     packed.push(pack(15, 0));
                     zeros -= 16;
                 }
-                packed.push(pack(zeros, coeff));
+                push_coeff(&mut packed, zeros, coeff);
                 zeros = 0;
             }
             // remaining zeros (if any) have a special symbol of 0, no
@@ -109,10 +213,10 @@ pub fn pack_coeffs(width: u32, height: u32, data: &[i16]) -> (Vec<u16>, Vec<u32>
         }
     }
 
-    // need to pad packed coefficients to a multiple of 512
-    let overage = packed.len() % 512;
+    // need to pad packed coefficients to a multiple of PACK_ALIGNMENT
+    let overage = packed.len() % PACK_ALIGNMENT;
     if overage > 0 {
-        let extra = 512 - overage;
+        let extra = PACK_ALIGNMENT - overage;
         packed.reserve(extra);
         for _ in 0..extra {
             packed.push(0);
@@ -121,3 +225,94 @@ pub fn pack_coeffs(width: u32, height: u32, data: &[i16]) -> (Vec<u16>, Vec<u32>
 
     (packed, indices)
 }
+
+// splits a packed value into its run of zeros and its coefficient. the value
+// may be ESCAPE, in which case the coefficient is the next word.
+pub fn unpack(p: u16) -> (u16, i16) {
+    let zeros: u16 = p >> 12;
+    let coeff: i16 = if (p & 0x800) == 0x800 {
+        ((p & 0xfff) | 0xf000) as i16
+    } else {
+        (p & 0xfff) as i16
+    };
+    (zeros, coeff)
+}
+
+// unpacks the block whose packed coefficients start at index, returning them
+// in natural order along with the index just past the block
+pub fn unpack_block(packed: &[u16], index: usize) -> ([i16; 64], usize) {
+    let zigzag = make_zigzag_table(8);
+    let mut block = [0; 64];
+    let mut index = index;
+    let mut zz_idx: usize = 0;
+    while zz_idx < 64 {
+        let p = packed[index];
+        index += 1;
+        if p == 0 {
+            break;
+        }
+        let (zeros, mut coeff) = unpack(p);
+        if (p & 0x0fff) == ESCAPE {
+            coeff = packed[index] as i16;
+            index += 1;
+        }
+        zz_idx += zeros as usize;
+        if coeff != 0 {
+            block[zigzag[zz_idx >> 3][zz_idx % 8]] = coeff;
+        }
+        zz_idx += 1;
+    }
+    (block, index)
+}
+
+// finds where the block whose packed coefficients start at index ends, without
+// trusting the packed data the way unpack_block does. returns None if the
+// block runs off the end of packed or past its 64 coefficients.
+pub fn packed_block_end(packed: &[u16], index: usize) -> Option<usize> {
+    let mut index = index;
+    let mut zz_idx: usize = 0;
+    while zz_idx < 64 {
+        let p = match packed.get(index) {
+            Some(&p) => p,
+            None => return None,
+        };
+        index += 1;
+        if p == 0 {
+            break;
+        }
+        if (p & 0x0fff) == ESCAPE {
+            if index >= packed.len() {
+                return None;
+            }
+            index += 1;
+        }
+        zz_idx += (p >> 12) as usize;
+        if zz_idx >= 64 {
+            return None;
+        }
+        zz_idx += 1;
+    }
+    Some(index)
+}
+
+// the CPU equivalent of the unpack shader, turning the output of pack_coeffs
+// back into a plane of coefficients
+pub fn unpack_coeffs(width: u32, height: u32, packed: &[u16], indices: &[u32]) -> Vec<i16> {
+    assert!(width % 8 == 0 && height % 8 == 0);
+    let block_width = (width >> 3) as usize;
+    let block_height = (height >> 3) as usize;
+    assert!(indices.len() == block_width * block_height);
+    let mut coeffs: Vec<i16> = vec![0; (width * height) as usize];
+    for by in 0..block_height {
+        for bx in 0..block_width {
+            let (block, _) = unpack_block(packed, indices[by * block_width + bx] as usize);
+            let block_offset = (by << 3) * (width as usize) + (bx << 3);
+            for j in 0..8 {
+                for i in 0..8 {
+                    coeffs[block_offset + j * (width as usize) + i] = block[j * 8 + i];
+                }
+            }
+        }
+    }
+    coeffs
+}
@@ -1,12 +1,15 @@
 #[macro_use]
 extern crate glium;
+extern crate clap;
 extern crate gpeg;
 
-use gpeg::{pack_coeffs, read_data, Plane};
-use std::borrow::Cow;
-use std::rc::Rc;
+use clap::{Arg, ArgGroup, ArgMatches, App};
+use gpeg::{container, parse_dims, parse_sampling, planefile, read_data, Plane};
+use gpeg::atlas::{self, Atlas};
+use gpeg::color::{ColorRange, ColorSpace, Upsampling};
+use gpeg::decoder::{Backend, ConvertMode, Decoder};
 use glium::{DisplayBuild, Surface};
-use glium::backend::Facade;
+use std::time::Instant;
 
 #[derive(Copy, Clone)]
 struct Vertex {
@@ -16,290 +19,228 @@ struct Vertex {
 
 implement_vertex!(Vertex, position, tex_coords);
 
-struct DecodeContext {
-    facade: Rc<glium::backend::Context>,
-    width: u32,
-    height: u32,
-    vertices: glium::vertex::VertexBuffer<Vertex>,
-    vertices_dec: glium::vertex::VertexBuffer<Vertex>,
-    program_unpack: glium::program::Program,
-    program_pass1: glium::program::Program,
-    program_pass2: glium::program::Program,
-    program_pass3: glium::program::Program,
-    program_convert: glium::program::Program,
-    pass1_top: glium::texture::IntegralTexture2d,
-    pass1_bot: glium::texture::IntegralTexture2d,
-    pass2_top: glium::texture::IntegralTexture2d,
-    pass2_bot: glium::texture::IntegralTexture2d,
+// loads text coefficient dumps for an image of the given size. the planes
+// must already be padded out to the MCU grid, and are already dequantized.
+fn load_planes(files: &[&str], width: u32, height: u32, sampling: &[(u32, u32)])
+               -> Result<(u32, u32, Atlas), String> {
+    if files.len() != 1 && files.len() != 3 {
+        return Err(format!("expected 1 or 3 planes, got {}", files.len()));
+    }
+    if files.len() != sampling.len() {
+        return Err(format!("expected sampling factors for each of the {} planes", files.len()));
+    }
+    let h_max = sampling.iter().map(|&(h, _)| h).max().unwrap();
+    let v_max = sampling.iter().map(|&(_, v)| v).max().unwrap();
+    // planes cover whole MCUs, and the conversion crops back to the image
+    let mcus_x = (width + 8 * h_max - 1) / (8 * h_max);
+    let mcus_y = (height + 8 * v_max - 1) / (8 * v_max);
+    let mut planes = vec![];
+    for (f, &(h, v)) in files.iter().zip(sampling.iter()) {
+        let plane_width = mcus_x * h * 8;
+        let plane_height = mcus_y * v * 8;
+        let data = try!(read_data(f).map_err(|e| format!("{}: {}", f, e)));
+        if data.len() != (plane_width * plane_height) as usize {
+            return Err(format!("{}: expected {}x{} coefficients, got {}",
+                               f, plane_width, plane_height, data.len()));
+        }
+        planes.push(Plane::new(plane_width, plane_height, h as u8, v as u8, &data, &[1; 64]));
+    }
+    Ok((width, height, Atlas::new(&planes)))
 }
 
-impl DecodeContext {
-    pub fn new(facade: Rc<glium::backend::Context>, width: u32, height: u32) -> DecodeContext {
-        let v1 = Vertex { position: [-1.0, -1.0], tex_coords: [0.0, 0.0] };
-        let v2 = Vertex { position: [-1.0, 1.0], tex_coords: [0.0, 1.0] };
-        let v3 = Vertex { position: [1.0, -1.0], tex_coords: [1.0, 0.0] };
-        let v4 = Vertex { position: [1.0, 1.0], tex_coords: [1.0, 1.0] };
-        let strip = vec![v1, v2, v3, v4];
-        let vertices = glium::VertexBuffer::new(&facade, &strip).unwrap();
-
-        let v1 = Vertex { position: [-1.0, -1.0], tex_coords: [0.0, 0.0] };
-        let v2 = Vertex { position: [-1.0, 0.0], tex_coords: [0.0, 1.0] };
-        let v3 = Vertex { position: [0.0, -1.0], tex_coords: [1.0, 0.0] };
-        let v4 = Vertex { position: [0.0, 0.0], tex_coords: [1.0, 1.0] };
-        let strip = vec![v1, v2, v3, v4];
-        let vertices_dec = glium::VertexBuffer::new(&facade, &strip).unwrap();
-
-        let vertex_shader_src = include_str!("thru.vs.glsl");
-        let fragment_shader_unpack_src = include_str!("unpack.fs.glsl");
-        let fragment_shader_pass1_src = include_str!("idct8x8_pass1.fs.glsl");
-        let fragment_shader_pass2_src = include_str!("idct8x8_pass2.fs.glsl");
-        let fragment_shader_pass3_src = include_str!("idct8x8_pass3.fs.glsl");
-        let fragment_shader_convert_src = include_str!("convert.fs.glsl");
-        let program_unpack = program!(
-            &facade,
-            140 => {
-                vertex: vertex_shader_src,
-                fragment: fragment_shader_unpack_src,
-            }
-        ).unwrap();
-        let program_pass1 = program!(
-            &facade,
-            140 => {
-                vertex: vertex_shader_src,
-                fragment: fragment_shader_pass1_src,
-            }
-        ).unwrap();
-        let program_pass2 = program!(
-            &facade,
-            140 => {
-                vertex: vertex_shader_src,
-                fragment: fragment_shader_pass2_src,
-            }
-        ).unwrap();
-        let program_pass3 = program!(
-            &facade,
-            140 => {
-                vertex: vertex_shader_src,
-                fragment: fragment_shader_pass3_src,
-            }
-        ).unwrap();
-        let program_convert = program!(
-            &facade,
-            140 => {
-                vertex: vertex_shader_src,
-                fragment: fragment_shader_convert_src,
-            }
-        ).unwrap();
+fn load_plane_file(path: &str, width: u32, height: u32)
+                   -> Result<(u32, u32, Atlas), String> {
+    let coeffs = try!(planefile::read_file(path).map_err(|e| format!("{}: {}", path, e)));
+    // four planes are taken to be uninverted CMYK
+    if coeffs.len() != 1 && coeffs.len() != 3 && coeffs.len() != 4 {
+        return Err(format!("{}: {} planes are not supported", path, coeffs.len()));
+    }
+    let planes: Vec<_> = coeffs.iter()
+        .map(|p| Plane::new(p.width, p.height, p.h, p.v, &p.data, &p.quant_table))
+        .collect();
+    Ok((width, height, Atlas::new(&planes)))
+}
 
-        // the intermediate textures can be width/8 because we only need hte
-        // first column of each block
-        let pass1_top = glium::texture::IntegralTexture2d::empty_with_format(
-            &facade,
-            glium::texture::UncompressedIntFormat::I16I16I16I16,
-            glium::texture::MipmapsOption::NoMipmap,
-            width / 8, height).unwrap();
-        let pass1_bot = glium::texture::IntegralTexture2d::empty_with_format(
-            &facade,
-            glium::texture::UncompressedIntFormat::I16I16I16I16,
-            glium::texture::MipmapsOption::NoMipmap,
-            width / 8, height).unwrap();
-        let pass2_top = glium::texture::IntegralTexture2d::empty_with_format(
-            &facade,
-            glium::texture::UncompressedIntFormat::I16I16I16I16,
-            glium::texture::MipmapsOption::NoMipmap,
-            width / 8, height).unwrap();
-        let pass2_bot = glium::texture::IntegralTexture2d::empty_with_format(
-            &facade,
-            glium::texture::UncompressedIntFormat::I16I16I16I16,
-            glium::texture::MipmapsOption::NoMipmap,
-            width / 8, height).unwrap();
+fn load_container(path: &str) -> Result<(u32, u32, Atlas), String> {
+    let image = try!(container::read_file(path).map_err(|e| format!("{}: {}", path, e)));
+    let mut atlas = Atlas::with_color_model(&image.planes, image.color_model);
+    atlas.precision = image.precision;
+    Ok((image.width, image.height, atlas))
+}
 
-        DecodeContext {
-            facade: facade,
-            width: width,
-            height: height,
-            vertices: vertices,
-            vertices_dec: vertices_dec,
-            program_unpack: program_unpack,
-            program_pass1: program_pass1,
-            program_pass2: program_pass2,
-            program_pass3: program_pass3,
-            program_convert: program_convert,
-            pass1_top: pass1_top,
-            pass1_bot: pass1_bot,
-            pass2_top: pass2_top,
-            pass2_bot: pass2_bot,
+fn load(matches: &ArgMatches) -> Result<(u32, u32, Atlas), String> {
+    if let Some(path) = matches.value_of("INPUT") {
+        // pre-packed images skip straight to upload
+        if path.ends_with(".gpeg") {
+            return load_container(path);
         }
+        return atlas::load_jpeg(path).map_err(|e| format!("{}: {}", path, e));
     }
+
+    let (width, height) = try!(parse_dims(matches.value_of("size").unwrap())
+                               .ok_or("size must be given as WxH".to_owned()));
+    if let Some(path) = matches.value_of("plane-file") {
+        return load_plane_file(path, width, height);
+    }
+
+    let files: Vec<&str> = matches.values_of("planes").unwrap().collect();
+    let sampling = try!(parse_sampling(matches.values_of("sampling"), files.len()));
+    load_planes(&files, width, height, &sampling)
 }
 
-fn decode_plane(ctx: &DecodeContext, plane: &Plane) -> glium::texture::IntegralTexture2d {
-    let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
-    let vertices = if plane.width == ctx.width && plane.height == ctx.height {
-        &ctx.vertices
+fn main() {
+    let matches = App::new("gpeg")
+        .about("Decodes an image on the GPU and displays it")
+        .arg(Arg::with_name("INPUT")
+             .help("JPEG or .gpeg file to decode")
+             .index(1))
+        .arg(Arg::with_name("planes")
+             .long("planes")
+             .value_name("FILE")
+             .help("Text coefficient dumps to decode instead, either Y or Y, Cb and Cr")
+             .takes_value(true)
+             .multiple(true)
+             .requires("size"))
+        .arg(Arg::with_name("plane-file")
+             .long("plane-file")
+             .value_name("FILE")
+             .help("Binary plane file to decode instead, as written by txt2plane")
+             .takes_value(true)
+             .requires("size"))
+        .arg(Arg::with_name("size")
+             .long("size")
+             .value_name("WxH")
+             .help("Image dimensions of the coefficient dumps or plane file")
+             .takes_value(true))
+        .arg(Arg::with_name("sampling")
+             .long("sampling")
+             .value_name("HxV")
+             .help("Sampling factors of each coefficient dump (default 2x2 1x1 1x1)")
+             .takes_value(true)
+             .multiple(true))
+        .group(ArgGroup::with_name("input")
+               .args(&["INPUT", "planes", "plane-file"])
+               .required(true))
+        .arg(Arg::with_name("compute")
+             .long("compute")
+             .help("Decodes with compute shaders, which needs OpenGL 4.3"))
+        .arg(Arg::with_name("blend")
+             .long("blend")
+             .help("Converts to RGB by blending the planes in the last iDCT pass")
+             .conflicts_with("compute"))
+        .arg(Arg::with_name("color-space")
+             .long("color-space")
+             .value_name("SPACE")
+             .help("Color space of the YCbCr samples (default 601)")
+             .possible_values(&["601", "709"])
+             .takes_value(true))
+        .arg(Arg::with_name("upsampling")
+             .long("upsampling")
+             .value_name("MODE")
+             .help("How chroma is upsampled (default nearest)")
+             .possible_values(&["nearest", "bilinear", "fancy"])
+             .takes_value(true))
+        .arg(Arg::with_name("limited-range")
+             .long("limited-range")
+             .help("Treats the YCbCr samples as limited range, as video usually is"))
+        .arg(Arg::with_name("redecode")
+             .long("redecode")
+             .help("Decodes the image again every frame and reports the frame rate"))
+        .get_matches();
+    let redecode = matches.is_present("redecode");
+    let backend = if matches.is_present("compute") {
+        Backend::Compute
     } else {
-        &ctx.vertices_dec
+        Backend::Fragment
     };
 
-    let index_image = glium::texture::RawImage2d {
-        data: Cow::Borrowed(&plane.packed_indices),
-        width: plane.width >> 3,
-        height: plane.height >> 3,
-        format: glium::texture::ClientFormat::U32,
+    let (width, height, atlas) = match load(&matches) {
+        Ok(image) => image,
+        Err(err) => {
+            println!("error: {}", err);
+            std::process::exit(1);
+        },
     };
-    let index_texture = glium::texture::UnsignedTexture2d::with_format(
-        &ctx.facade,
-        index_image,
-        glium::texture::UncompressedUintFormat::U32,
-        glium::texture::MipmapsOption::NoMipmap).unwrap();
 
-    let packed_texture = glium::texture::buffer_texture::BufferTexture::new(
-        &ctx.facade,
-        &plane.packed_coeffs,
-        glium::texture::buffer_texture::BufferTextureType::Unsigned).unwrap();
+    // fit the window to the image, shrinking large images to fit on screen
+    let scale = (1.0f32).min(1024.0 / width as f32).min(1024.0 / height as f32);
+    let window_width = ((width as f32 * scale) as u32).max(1);
+    let window_height = ((height as f32 * scale) as u32).max(1);
+    let display = glium::glutin::WindowBuilder::new()
+        .with_dimensions(window_width, window_height)
+        .build_glium().unwrap();
 
-    let output_unpack = glium::texture::IntegralTexture2d::empty_with_format(
-        &ctx.facade,
-        glium::texture::UncompressedIntFormat::I16,
-        glium::texture::MipmapsOption::NoMipmap,
-        plane.width, plane.height).unwrap();
-    let uniforms_unpack = uniform! {
-        plane_dims: [plane.width as i32, plane.height as i32],
-        index_texture: &index_texture,
-        packed_coeffs: &packed_texture,
+    let mut decoder = match Decoder::with_backend(&display, atlas.width, atlas.height, backend) {
+        Some(decoder) => decoder,
+        None => {
+            println!("error: the {:?} backend isn't supported by this context", backend);
+            std::process::exit(1);
+        },
     };
-    {
-        let mut target_unpack = glium::framebuffer::SimpleFrameBuffer::new(
-            &ctx.facade,
-            &output_unpack).unwrap();
-        target_unpack.draw(
-            &ctx.vertices,
-            &indices,
-            &ctx.program_unpack,
-            &uniforms_unpack,
-            &Default::default()).unwrap();
+    if matches.is_present("blend") {
+        decoder.set_convert_mode(ConvertMode::Blend);
     }
-    {
-        let uniforms_pass1 = uniform! {
-            plane_dims: [plane.width as i32, plane.height as i32],
-            data: &output_unpack,
-        };
-        let output_pass1 = [
-            ("pass1_top", &ctx.pass1_top),
-            ("pass1_bot", &ctx.pass1_bot),
-        ];
-        let mut target1 = glium::framebuffer::MultiOutputFrameBuffer::new(
-            &ctx.facade,
-            output_pass1.iter().cloned()).unwrap();
-        target1.draw(vertices, &indices, &ctx.program_pass1, &uniforms_pass1,
-                     &Default::default()).unwrap();
+    if matches.value_of("color-space") == Some("709") {
+        decoder.set_color_space(ColorSpace::Bt709);
     }
-    let uniforms_pass2 = uniform! {
-        plane_dims: [plane.width as i32, plane.height as i32],
-        pass1_top: &ctx.pass1_top,
-        pass1_bot: &ctx.pass1_bot,
-    };
-    let output_pass2 = [
-        ("pass2_top", &ctx.pass2_top),
-        ("pass2_bot", &ctx.pass2_bot),
-    ];
-    let mut target2 = glium::framebuffer::MultiOutputFrameBuffer::new(
-        &ctx.facade,
-        output_pass2.iter().cloned()).unwrap();
-    target2.draw(vertices, &indices, &ctx.program_pass2, &uniforms_pass2,
-                 &Default::default()).unwrap();
-    let uniforms_pass3 = uniform! {
-        plane_dims: [plane.width as i32, plane.height as i32],
-        pass2_top: &ctx.pass2_top,
-        pass2_bot: &ctx.pass2_bot,
-    };
-    {
-        let mut target3 = glium::framebuffer::SimpleFrameBuffer::new(
-            &ctx.facade,
-            &output_unpack).unwrap();
-        target3.draw(vertices, &indices, &ctx.program_pass3, &uniforms_pass3,
-                     &Default::default()).unwrap();
+    if matches.is_present("limited-range") {
+        decoder.set_color_range(ColorRange::Limited);
     }
-    output_unpack
-}
+    match matches.value_of("upsampling") {
+        Some("bilinear") => decoder.set_upsampling(Upsampling::Bilinear),
+        Some("fancy") => decoder.set_upsampling(Upsampling::Fancy),
+        _ => {},
+    }
+    let mut image = decoder.decode(width, height, &atlas);
 
-fn convert_planes(ctx: &DecodeContext, width: u32, height: u32,
-                  textures: &Vec<glium::texture::IntegralTexture2d>)
-                  -> glium::texture::Texture2d {
-    let output = glium::texture::Texture2d::empty_with_format(
-        &ctx.facade,
-        glium::texture::UncompressedFloatFormat::U8U8U8,
-        glium::texture::MipmapsOption::NoMipmap,
-        width, height).unwrap();
+    // the window has the image's aspect ratio, so the image fills it
+    let v1 = Vertex { position: [-1.0, -1.0], tex_coords: [0.0, 1.0] };
+    let v2 = Vertex { position: [-1.0, 1.0], tex_coords: [0.0, 0.0] };
+    let v3 = Vertex { position: [1.0, -1.0], tex_coords: [1.0, 1.0] };
+    let v4 = Vertex { position: [1.0, 1.0], tex_coords: [1.0, 0.0] };
+    let strip = vec![v1, v2, v3, v4];
+    let vertices = glium::VertexBuffer::new(&display, &strip).unwrap();
     let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
-    let uniforms = uniform! {
-        plane_dims: [width as i32, height as i32],
-        y_plane: &textures[0],
-        cb_plane: &textures[1],
-        cr_plane: &textures[2],
-    };
-    {
-        let mut target = glium::framebuffer::SimpleFrameBuffer::new( &ctx.facade, &output).unwrap();
-        target.draw(&ctx.vertices, &indices, &ctx.program_convert, &uniforms,
-                    &Default::default()).unwrap();
-    }
-    output
-}
 
-fn main() {
-    let display = glium::glutin::WindowBuilder::new().with_dimensions(1024, 1024).build_glium().unwrap();
-    loop {
-        let width = 1024;
-        let height = 576;
+    let vertex_shader_src = include_str!("thru.vs.glsl");
+    let fragment_shader_src = include_str!("simple.fs.glsl");
+    let program = program!(
+        &display,
+        140 => {
+            vertex: vertex_shader_src,
+            fragment: fragment_shader_src,
+            outputs_srgb: true,
+        }
+    ).unwrap();
 
-        let raw_planes = vec![(width, height, "f1.Y"),
-                              (width >> 1, height >> 1, "f1.Cb"),
-                              (width >> 1, height >> 1, "f1.Cr")];
-        let planes: Vec<Plane> = raw_planes.iter().map(|&(w, h, f)| {
-                let data = read_data(f);
-                assert!((w * h) as usize == data.len());
-                let (packed_coeffs, packed_indices) = pack_coeffs(w, h, &data);
-                Plane {
-                    width: w,
-                    height: h,
-                    packed_coeffs: packed_coeffs,
-                    packed_indices: packed_indices,
-                }
-        }).collect();
+    let mut frames = 0;
+    let mut last_report = Instant::now();
+    loop {
+        if redecode {
+            image = decoder.decode(width, height, &atlas);
+        }
 
-        let ctx = DecodeContext::new(display.get_context().clone(), width, height);
-        let output: Vec<_> = planes.iter().map(|p| decode_plane(&ctx, p)).collect();
-        let image = convert_planes(&ctx, width, height, &output);
+        {
+            let uniforms = uniform! {
+                tex: &image,
+            };
 
-        // 16:9
-        let v1 = Vertex { position: [-0.75, -0.09375], tex_coords: [0.0, 1.0] };
-        let v2 = Vertex { position: [-0.75, 0.75], tex_coords: [0.0, 0.0] };
-        let v3 = Vertex { position: [0.75, -0.09375], tex_coords: [1.0, 1.0] };
-        let v4 = Vertex { position: [0.75, 0.75], tex_coords: [1.0, 0.0] };
-        let strip = vec![v1, v2, v3, v4];
-        let vertices = glium::VertexBuffer::new(&display, &strip).unwrap();
-        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+            let mut target = display.draw();
+            target.clear_color(0.0, 0.0, 0.0, 1.0);
+            target.draw(&vertices, &indices, &program, &uniforms, &Default::default()).unwrap();
+            target.finish().unwrap();
+        }
 
-        let vertex_shader_src = include_str!("thru.vs.glsl");
-        let fragment_shader_src = include_str!("simple.fs.glsl");
-        let program = program!(
-            &display,
-            140 => {
-                vertex: vertex_shader_src,
-                fragment: fragment_shader_src,
-                outputs_srgb: true,
+        if redecode {
+            frames += 1;
+            let elapsed = last_report.elapsed();
+            if elapsed.as_secs() >= 1 {
+                let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+                println!("{:.1} decodes/s", frames as f64 / secs);
+                frames = 0;
+                last_report = Instant::now();
             }
-        ).unwrap();
-
-
-        let uniforms = uniform! {
-            tex: &image,
-        };
-
-        let mut target = display.draw();
-        target.clear_color(0.0, 0.0, 0.0, 1.0);
-        target.draw(&vertices, &indices, &program, &uniforms, &Default::default()).unwrap();
-        target.finish().unwrap();
+        }
 
         for ev in display.poll_events() {
             match ev {
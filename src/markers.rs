@@ -0,0 +1,475 @@
+use make_zigzag_table;
+use std::error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    UnknownMarker(u8),
+    Unsupported(&'static str),
+    Malformed(&'static str),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "i/o error: {}", err),
+            Error::UnknownMarker(code) => write!(f, "unknown marker {:x}", code),
+            Error::Unsupported(what) => write!(f, "unsupported: {}", what),
+            Error::Malformed(what) => write!(f, "malformed: {}", what),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(ref err) => err.description(),
+            Error::UnknownMarker(_) => "unknown marker",
+            Error::Unsupported(what) => what,
+            Error::Malformed(what) => what,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FrameType {
+    BaselineDct,
+    // sequential like baseline, but allowing 12-bit samples and more tables
+    ExtendedDct,
+    ProgressiveDct,
+    // the same as extended and progressive but arithmetic coded
+    ExtendedArithmeticDct,
+    ProgressiveArithmeticDct,
+}
+
+impl FrameType {
+    pub fn is_progressive(&self) -> bool {
+        match *self {
+            FrameType::ProgressiveDct | FrameType::ProgressiveArithmeticDct => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        match *self {
+            FrameType::ExtendedArithmeticDct | FrameType::ProgressiveArithmeticDct => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SegmentType {
+    StartOfImage,
+    App(u8),
+    Comment,
+    DefineQuantizationTable,
+    StartOfFrame(FrameType),
+    DefineHuffmanTable,
+    DefineArithmeticConditioning,
+    StartOfScan,
+    DefineRestartInterval,
+    Restart(u8),
+    EndOfImage
+}
+
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub id: u8,
+    pub h: u8,
+    pub v: u8,
+    pub tq: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameHeader {
+    pub frame_type: FrameType,
+    pub precision: u8,
+    pub width: u16,
+    pub height: u16,
+    pub components: Vec<Component>,
+}
+
+impl FrameHeader {
+    pub fn h_max(&self) -> u8 {
+        self.components.iter().map(|c| c.h).max().unwrap_or(1)
+    }
+
+    pub fn v_max(&self) -> u8 {
+        self.components.iter().map(|c| c.v).max().unwrap_or(1)
+    }
+
+    pub fn mcus_x(&self) -> u32 {
+        let mcu_width = 8 * self.h_max() as u32;
+        (self.width as u32 + mcu_width - 1) / mcu_width
+    }
+
+    pub fn mcus_y(&self) -> u32 {
+        let mcu_height = 8 * self.v_max() as u32;
+        (self.height as u32 + mcu_height - 1) / mcu_height
+    }
+
+    // the number of blocks covering just the component itself, which is what
+    // a non-interleaved scan codes
+    pub fn component_blocks(&self, c: &Component) -> (u32, u32) {
+        let (h_max, v_max) = (self.h_max() as u32, self.v_max() as u32);
+        let w = (self.width as u32 * c.h as u32 + h_max - 1) / h_max;
+        let h = (self.height as u32 * c.v as u32 + v_max - 1) / v_max;
+        ((w + 7) / 8, (h + 7) / 8)
+    }
+}
+
+// quantization table values are stored in natural (not zigzag) order
+#[derive(Debug, Clone)]
+pub struct QuantTable {
+    pub id: u8,
+    pub values: Vec<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HuffmanTable {
+    pub class: u8,
+    pub id: u8,
+    pub counts: [u8; 16],
+    pub values: Vec<u8>,
+}
+
+// conditions the statistics of an arithmetic coding table as in F.1.4.4. a DC
+// table's value holds the L bounds in its low nibble and U in its high one, and
+// an AC table's value is Kx.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArithmeticConditioning {
+    pub class: u8,
+    pub id: u8,
+    pub value: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanComponent {
+    pub id: u8,
+    pub td: u8,
+    pub ta: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanHeader {
+    pub components: Vec<ScanComponent>,
+    pub ss: u8,
+    pub se: u8,
+    pub ah: u8,
+    pub al: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct AppSegment {
+    pub n: u8,
+    pub data: Vec<u8>,
+}
+
+// the APP14 segment Adobe writes, whose transform says what the components
+// are: 0 is RGB or CMYK, 1 is YCbCr and 2 is YCCK
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdobeSegment {
+    pub version: u16,
+    pub flags0: u16,
+    pub flags1: u16,
+    pub transform: u8,
+}
+
+impl AdobeSegment {
+    // returns None if the segment isn't an Adobe one, since other
+    // applications use APP14 too
+    pub fn parse(app: &AppSegment) -> Option<AdobeSegment> {
+        let d = &app.data;
+        if app.n != 14 || d.len() < 12 || &d[0..5] != b"Adobe" {
+            return None;
+        }
+        let word = |i: usize| ((d[i] as u16) << 8) | (d[i + 1] as u16);
+        Some(AdobeSegment {
+            version: word(5),
+            flags0: word(7),
+            flags1: word(9),
+            transform: d[11],
+        })
+    }
+}
+
+fn read_byte(input: &mut Iterator<Item=io::Result<u8>>) -> Result<u8, Error> {
+    match input.next() {
+        Some(b) => Ok(try!(b)),
+        None => Err(Error::Malformed("unexpected end of file")),
+    }
+}
+
+fn read_word(input: &mut Iterator<Item=io::Result<u8>>) -> Result<u16, Error> {
+    let hi = try!(read_byte(input));
+    let lo = try!(read_byte(input));
+    Ok(((hi as u16) << 8) | (lo as u16))
+}
+
+pub fn skip(input: &mut Iterator<Item=io::Result<u8>>, bytes: usize) -> Result<(), Error> {
+    for _ in 0..bytes {
+        try!(read_byte(input));
+    }
+    Ok(())
+}
+
+// returns the segment type and the length of the segment data following the
+// length field
+pub fn parse_header(input: &mut Iterator<Item=io::Result<u8>>, hi: u8, lo: u8)
+                    -> Result<(SegmentType, usize), Error> {
+    if hi != 0xff {
+        return Err(Error::Malformed("expected a marker"));
+    }
+    let ty = match lo {
+        0xd8 => SegmentType::StartOfImage,
+        code @ 0xe0...0xef => SegmentType::App(code & 0xf),
+        0xfe => SegmentType::Comment,
+        0xdb => SegmentType::DefineQuantizationTable,
+        0xc0 => SegmentType::StartOfFrame(FrameType::BaselineDct),
+        0xc1 => SegmentType::StartOfFrame(FrameType::ExtendedDct),
+        0xc2 => SegmentType::StartOfFrame(FrameType::ProgressiveDct),
+        0xc9 => SegmentType::StartOfFrame(FrameType::ExtendedArithmeticDct),
+        0xca => SegmentType::StartOfFrame(FrameType::ProgressiveArithmeticDct),
+        0xc3 | 0xc5...0xc7 | 0xcb | 0xcd...0xcf => {
+            return Err(Error::Unsupported("frame type"));
+        },
+        0xc4 => SegmentType::DefineHuffmanTable,
+        0xcc => SegmentType::DefineArithmeticConditioning,
+        0xda => SegmentType::StartOfScan,
+        0xdd => SegmentType::DefineRestartInterval,
+        code @ 0xd0...0xd7 => SegmentType::Restart(code & 0x7),
+        0xd9 => SegmentType::EndOfImage,
+        code => return Err(Error::UnknownMarker(code)),
+    };
+    let len = match ty {
+        SegmentType::StartOfImage => 0,
+        SegmentType::EndOfImage => 0,
+        SegmentType::Restart(_) => 0,
+        _ => {
+            let len = try!(read_word(input)) as usize;
+            if len < 2 {
+                return Err(Error::Malformed("segment length"));
+            }
+            len - 2
+        },
+    };
+    Ok((ty, len))
+}
+
+pub fn read_header(input: &mut Iterator<Item=io::Result<u8>>) -> Result<(SegmentType, usize), Error> {
+    let hi = try!(read_byte(input));
+    let lo = try!(read_byte(input));
+    parse_header(input, hi, lo)
+}
+
+// skips over entropy coded data (or anything else) to the next marker. restart
+// markers only ever appear inside entropy coded data so they are skipped too.
+pub fn find_marker(input: &mut Iterator<Item=io::Result<u8>>) -> Result<(SegmentType, usize), Error> {
+    loop {
+        let hi = try!(read_byte(input));
+        if hi == 0xff {
+            let mut lo = try!(read_byte(input));
+            // any number of 0xff fill bytes may precede a marker
+            while lo == 0xff {
+                lo = try!(read_byte(input));
+            }
+            if lo != 0x00 && (lo < 0xd0 || lo > 0xd7) {
+                return parse_header(input, hi, lo);
+            }
+        }
+    }
+}
+
+// a single segment may define several tables
+pub fn read_quantization_table(input: &mut Iterator<Item=io::Result<u8>>, len: usize)
+                               -> Result<Vec<QuantTable>, Error> {
+    let zigzag = make_zigzag_table(8);
+    let mut tables = vec![];
+    let mut remaining = len;
+    while remaining > 0 {
+        let pqtq = try!(read_byte(input));
+        // pq=1 tables have 16-bit values, which 12-bit frames need
+        let pq = (pqtq & 0xf0) >> 4;
+        let tq = pqtq & 0x0f;
+        if pq > 1 || tq > 3 {
+            return Err(Error::Malformed("quantization table precision or id"));
+        }
+        let table_len = if pq == 0 { 65 } else { 129 };
+        if remaining < table_len {
+            return Err(Error::Malformed("quantization table length"));
+        }
+        let mut values = vec![0; 64];
+        for j in 0..8 {
+            for i in 0..8 {
+                values[zigzag[j][i]] = if pq == 0 {
+                    try!(read_byte(input)) as u16
+                } else {
+                    try!(read_word(input))
+                };
+            }
+        }
+        tables.push(QuantTable { id: tq, values: values });
+        remaining -= table_len;
+    }
+    Ok(tables)
+}
+
+pub fn read_frame_header(input: &mut Iterator<Item=io::Result<u8>>, frame_type: FrameType,
+                         len: usize) -> Result<FrameHeader, Error> {
+    if len < 6 {
+        return Err(Error::Malformed("frame header length"));
+    }
+    let p = try!(read_byte(input));
+    let y = try!(read_word(input));
+    let x = try!(read_word(input));
+    let nf = try!(read_byte(input));
+    if len != 6 + 3 * nf as usize {
+        return Err(Error::Malformed("frame header length"));
+    }
+
+    let mut components = vec![];
+    for _ in 0..nf {
+        let c = try!(read_byte(input));
+        let hv = try!(read_byte(input));
+        let h = (hv & 0xf0) >> 4;
+        let v = hv & 0x0f;
+        let tq = try!(read_byte(input));
+        if h < 1 || h > 4 || v < 1 || v > 4 || tq > 3 {
+            return Err(Error::Malformed("frame component"));
+        }
+        components.push(Component { id: c, h: h, v: v, tq: tq });
+    }
+    if components.is_empty() {
+        return Err(Error::Malformed("frame has no components"));
+    }
+
+    Ok(FrameHeader {
+        frame_type: frame_type,
+        precision: p,
+        width: x,
+        height: y,
+        components: components,
+    })
+}
+
+// a single segment may define several tables
+pub fn read_huffman_table(input: &mut Iterator<Item=io::Result<u8>>, len: usize)
+                          -> Result<Vec<HuffmanTable>, Error> {
+    let mut tables = vec![];
+    let mut remaining = len;
+    while remaining > 0 {
+        if remaining < 17 {
+            return Err(Error::Malformed("huffman table length"));
+        }
+        let tcth = try!(read_byte(input));
+        let tc = (tcth & 0xf0) >> 4;
+        let th = tcth & 0x0f;
+        if tc > 1 || th > 3 {
+            return Err(Error::Malformed("huffman table class or id"));
+        }
+        let mut counts = [0; 16];
+        let mut total = 0;
+        for l in 0..16 {
+            counts[l] = try!(read_byte(input));
+            total += counts[l] as usize;
+        }
+        if remaining < 17 + total {
+            return Err(Error::Malformed("huffman table length"));
+        }
+        let mut values = Vec::with_capacity(total);
+        for _ in 0..total {
+            values.push(try!(read_byte(input)));
+        }
+        tables.push(HuffmanTable { class: tc, id: th, counts: counts, values: values });
+        remaining -= 17 + total;
+    }
+    Ok(tables)
+}
+
+// a single segment may condition several tables
+pub fn read_arithmetic_conditioning(input: &mut Iterator<Item=io::Result<u8>>, len: usize)
+                                    -> Result<Vec<ArithmeticConditioning>, Error> {
+    if len % 2 != 0 {
+        return Err(Error::Malformed("arithmetic conditioning length"));
+    }
+    let mut tables = vec![];
+    for _ in 0..len / 2 {
+        let tctb = try!(read_byte(input));
+        let tc = (tctb & 0xf0) >> 4;
+        let tb = tctb & 0x0f;
+        let value = try!(read_byte(input));
+        if tc > 1 || tb > 3 {
+            return Err(Error::Malformed("arithmetic conditioning class or id"));
+        }
+        let valid = match tc {
+            0 => (value & 0x0f) <= (value >> 4),
+            _ => value >= 1 && value <= 63,
+        };
+        if !valid {
+            return Err(Error::Malformed("arithmetic conditioning value"));
+        }
+        tables.push(ArithmeticConditioning { class: tc, id: tb, value: value });
+    }
+    Ok(tables)
+}
+
+pub fn read_scan_header(input: &mut Iterator<Item=io::Result<u8>>, len: usize)
+                        -> Result<ScanHeader, Error> {
+    if len < 1 {
+        return Err(Error::Malformed("scan header length"));
+    }
+    let ns = try!(read_byte(input));
+    if ns < 1 || ns > 4 {
+        return Err(Error::Malformed("number of scan components"));
+    }
+    if len != 4 + 2 * ns as usize {
+        return Err(Error::Malformed("scan header length"));
+    }
+    let mut components = vec![];
+    for _ in 0..ns {
+        let cs = try!(read_byte(input));
+        let tdta = try!(read_byte(input));
+        let td = (tdta & 0xf0) >> 4;
+        let ta = tdta & 0x0f;
+        if td > 3 || ta > 3 {
+            return Err(Error::Malformed("scan huffman table id"));
+        }
+        components.push(ScanComponent { id: cs, td: td, ta: ta });
+    }
+    let ss = try!(read_byte(input));
+    let se = try!(read_byte(input));
+    let ahal = try!(read_byte(input));
+    Ok(ScanHeader {
+        components: components,
+        ss: ss,
+        se: se,
+        ah: (ahal & 0xf0) >> 4,
+        al: ahal & 0x0f,
+    })
+}
+
+pub fn read_restart_interval(input: &mut Iterator<Item=io::Result<u8>>, len: usize)
+                             -> Result<u16, Error> {
+    if len != 2 {
+        return Err(Error::Malformed("restart interval length"));
+    }
+    read_word(input)
+}
+
+pub fn read_app_segment(input: &mut Iterator<Item=io::Result<u8>>, n: u8, len: usize)
+                        -> Result<AppSegment, Error> {
+    let mut data = Vec::with_capacity(len);
+    for _ in 0..len {
+        data.push(try!(read_byte(input)));
+    }
+    Ok(AppSegment { n: n, data: data })
+}
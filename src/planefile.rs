@@ -0,0 +1,149 @@
+// a compact binary format for coefficient planes, replacing the text dumps.
+// each plane is a 16 byte header followed by the coefficients as little endian
+// i16 values in the layout pack_coeffs expects. several planes may be stored
+// back to back in one file.
+//
+//   0  magic "GPCP"
+//   4  width (u32)
+//   8  height (u32)
+//  12  component id (u8)
+//  13  horizontal sampling factor (u8)
+//  14  vertical sampling factor (u8)
+//  15  flags (u8)
+//
+// if bit 0 of the flags is set, the header is followed by a quantization table
+// of 64 u16 values in natural order. otherwise the coefficients are already
+// dequantized.
+
+use binio::{read_exact, read_full, read_u16, read_u32, write_u16, write_u32};
+use jpeg::CoeffPlane;
+use markers::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+const MAGIC: &'static [u8; 4] = b"GPCP";
+
+const FLAG_QUANT_TABLE: u8 = 1;
+
+// largest chunk of coefficients converted at once while streaming
+const CHUNK_SIZE: usize = 4096;
+
+// reads the next plane, or returns None at the end of the input
+pub fn read_plane(input: &mut Read) -> Result<Option<CoeffPlane>, Error> {
+    let mut header = [0; 16];
+    if !try!(read_full(input, &mut header)) {
+        return Ok(None);
+    }
+    if &header[0..4] != &MAGIC[..] {
+        return Err(Error::Malformed("not a plane file"));
+    }
+    let width = read_u32(&header[4..8]);
+    let height = read_u32(&header[8..12]);
+    let (id, h, v, flags) = (header[12], header[13], header[14], header[15]);
+    if (flags & !FLAG_QUANT_TABLE) != 0 {
+        return Err(Error::Unsupported("plane file flags"));
+    }
+    if width == 0 || height == 0 || width % 8 != 0 || height % 8 != 0 {
+        return Err(Error::Malformed("plane dimensions"));
+    }
+    if h < 1 || h > 4 || v < 1 || v > 4 {
+        return Err(Error::Malformed("plane sampling factors"));
+    }
+    let len = try!((width as usize).checked_mul(height as usize)
+                   .ok_or(Error::Malformed("plane dimensions")));
+
+    let mut quant_table = vec![1; 64];
+    if (flags & FLAG_QUANT_TABLE) != 0 {
+        let mut buf = [0; 128];
+        try!(read_exact(input, &mut buf));
+        for (q, pair) in quant_table.iter_mut().zip(buf.chunks(2)) {
+            *q = read_u16(pair);
+        }
+        if quant_table.iter().any(|&q| q == 0) {
+            return Err(Error::Malformed("zero in quantization table"));
+        }
+    }
+
+    // the dimensions haven't been checked against the length of the input, so
+    // only grow the plane as its coefficients are actually read
+    let mut data = Vec::with_capacity(len.min(CHUNK_SIZE));
+    let mut buf = [0; CHUNK_SIZE * 2];
+    while data.len() < len {
+        let n = (len - data.len()).min(CHUNK_SIZE);
+        try!(read_exact(input, &mut buf[..n * 2]));
+        for pair in buf[..n * 2].chunks(2) {
+            data.push(((pair[0] as u16) | ((pair[1] as u16) << 8)) as i16);
+        }
+    }
+
+    Ok(Some(CoeffPlane {
+        id: id,
+        h: h,
+        v: v,
+        width: width,
+        height: height,
+        data: data,
+        quant_table: quant_table,
+    }))
+}
+
+// reads every plane up to the end of the input
+pub fn read_planes(input: &mut Read) -> Result<Vec<CoeffPlane>, Error> {
+    let mut planes = vec![];
+    while let Some(plane) = try!(read_plane(input)) {
+        planes.push(plane);
+    }
+    if planes.is_empty() {
+        return Err(Error::Malformed("plane file is empty"));
+    }
+    Ok(planes)
+}
+
+pub fn write_plane(output: &mut Write, plane: &CoeffPlane) -> io::Result<()> {
+    assert!(plane.data.len() == (plane.width * plane.height) as usize);
+    assert!(plane.quant_table.len() == 64);
+    let mut header = [0; 16];
+    header[0..4].copy_from_slice(MAGIC);
+    write_u32(&mut header[4..8], plane.width);
+    write_u32(&mut header[8..12], plane.height);
+    header[12] = plane.id;
+    header[13] = plane.h;
+    header[14] = plane.v;
+    let quantized = plane.quant_table.iter().any(|&q| q != 1);
+    if quantized {
+        header[15] = FLAG_QUANT_TABLE;
+    }
+    try!(output.write_all(&header));
+
+    if quantized {
+        let mut buf = [0; 128];
+        for (i, &q) in plane.quant_table.iter().enumerate() {
+            write_u16(&mut buf[i * 2..], q);
+        }
+        try!(output.write_all(&buf));
+    }
+
+    let mut buf = [0; CHUNK_SIZE * 2];
+    for chunk in plane.data.chunks(CHUNK_SIZE) {
+        for (i, &coeff) in chunk.iter().enumerate() {
+            buf[i * 2] = coeff as u8;
+            buf[i * 2 + 1] = ((coeff as u16) >> 8) as u8;
+        }
+        try!(output.write_all(&buf[..chunk.len() * 2]));
+    }
+    Ok(())
+}
+
+pub fn read_file(path: &str) -> Result<Vec<CoeffPlane>, Error> {
+    let f = try!(File::open(path));
+    read_planes(&mut BufReader::new(f))
+}
+
+pub fn write_file(path: &str, planes: &[CoeffPlane]) -> io::Result<()> {
+    let f = try!(File::create(path));
+    let mut output = BufWriter::new(f);
+    for plane in planes {
+        try!(write_plane(&mut output, plane));
+    }
+    output.flush()
+}
@@ -6,8 +6,21 @@ out int color;
 
 uniform ivec2 plane_dims;
 uniform usampler2D index_texture;
+uniform usampler2D quant_table;
 uniform usamplerBuffer packed_coeffs;
 
+// the first block row of each plane in the atlas, unused planes being past
+// the end of it
+uniform ivec4 plane_rows;
+
+// the planes' quantization tables are stacked in the same order as the planes,
+// so this finds the first row of the table for a block row
+int quant_row(int block_row) {
+  int plane = int(block_row >= plane_rows.y) + int(block_row >= plane_rows.z) +
+    int(block_row >= plane_rows.w);
+  return plane * 8;
+}
+
 int ZIGZAG[64] = int[64](
    0,  1,  5,  6, 14, 15, 27, 28,
    2,  4,  7, 13, 16, 26, 29, 42,
@@ -44,7 +57,15 @@ void main() {
     
     int zeros = int((packed_coeff >> 12) & uint(0x000f));
     int coeff;
-    if ((packed_coeff & uint(0x800)) == uint(0x800)) {
+    if ((packed_coeff & uint(0x0fff)) == uint(0x800)) {
+      // escaped coefficients are stored whole in the next word
+      index += 1;
+      uint escaped = texelFetch(packed_coeffs, index).r;
+      coeff = int(escaped);
+      if ((escaped & uint(0x8000)) == uint(0x8000)) {
+        coeff -= 0x10000;
+      }
+    } else if ((packed_coeff & uint(0x800)) == uint(0x800)) {
       coeff = int(uint(~0x0fff) | (packed_coeff & uint(0x0fff)));
     } else {
       coeff = int(packed_coeff & uint(0x0fff));
@@ -65,5 +86,7 @@ void main() {
     turns += 1;
   }
   
-  color = c;
+  // dequantize, saturating to what the output can hold
+  int q = int(texelFetch(quant_table, ivec2(offset.x, quant_row(block.y) + offset.y), 0).r);
+  color = clamp(c * q, -32768, 32767);
 }
@@ -0,0 +1,179 @@
+#version 140
+
+// we want to truncate toward zero, but normal rshift truncates to -inf
+#define UNBIASED_RSHIFT1(a) (((a) - ((a) >> 31)) >> 1)
+
+// 1D iDCT takes a row and outputs a column
+void idct8(out int x[8], const int y[8]) {
+  int t0;
+  int t1;
+  int t1h;
+  int t2;
+  int t3;
+  int t4;
+  int t4h;
+  int t5;
+  int t6;
+  int t6h;
+  int t7;
+  t0 = y[0];
+  t1 = y[1];
+  t2 = y[2];
+  t3 = y[3];
+  t4 = y[4];
+  t5 = y[5];
+  t6 = y[6];
+  t7 = y[7];
+  t5 -= (t3*2485 + 4096) >> 13;
+  t3 += (t5*18205 + 16384) >> 15;
+  t5 -= (t3*2485 + 4096) >> 13;
+  t7 -= (t1*3227 + 16384) >> 15;
+  t1 += (t7*6393 + 16384) >> 15;
+  t7 -= (t1*3227 + 16384) >> 15;
+  t1 += t3;
+  t1h = UNBIASED_RSHIFT1(t1);
+  t3 = t1h - t3;
+  t5 += t7;
+  t7 = UNBIASED_RSHIFT1(t5) - t7;
+  t3 += (t5*7489 + 4096) >> 13;
+  t5 -= (t3*11585 + 8192) >> 14;
+  t3 -= (t5*19195 + 16384) >> 15;
+  t6 += (t2*21895 + 16384) >> 15;
+  t2 -= (t6*15137 + 8192) >> 14;
+  t6 += (t2*21895 + 16384) >> 15;
+  t0 += (t4*13573 + 16384) >> 15;
+  t4 -= (t0*11585 + 8192) >> 14;
+  t0 += (t4*13573 + 16384) >> 15;
+  t4 = t2 - t4;
+  t4h = UNBIASED_RSHIFT1(t4);
+  t2 = t4h - t2;
+  t6 = t0 - t6;
+  t6h = UNBIASED_RSHIFT1(t6);
+  t0 -= t6h;
+  t7 = t6h - t7;
+  t6 -= t7;
+  t2 += UNBIASED_RSHIFT1(t3);
+  t3 = t2 - t3;
+  t5 += t4h;
+  t4 -= t5;
+  t0 += t1h;
+  t1 = t0 - t1;
+  x[0] = t0;
+  x[1] = t4;
+  x[2] = t2;
+  x[3] = t6;
+  x[4] = t7;
+  x[5] = t3;
+  x[6] = t5;
+  x[7] = t1;
+}
+
+in vec2 v_tex_coords;
+
+out ivec4 pass1_top;
+out ivec4 pass1_bot;
+
+uniform ivec2 plane_dims;
+uniform usampler2D index_texture;
+uniform usampler2D quant_table;
+uniform usamplerBuffer packed_coeffs;
+
+// the first block row of each plane in the atlas, unused planes being past
+// the end of it
+uniform ivec4 plane_rows;
+
+// how far the coefficients are shifted up for headroom in the transform,
+// which depends on the sample precision
+uniform int headroom;
+
+// the planes' quantization tables are stacked in the same order as the planes,
+// so this finds the first row of the table for a block row
+int quant_row(int block_row) {
+  int plane = int(block_row >= plane_rows.y) + int(block_row >= plane_rows.z) +
+    int(block_row >= plane_rows.w);
+  return plane * 8;
+}
+
+// the natural order position of each zigzag position
+int NATURAL[64] = int[64](
+   0,  1,  8, 16,  9,  2,  3, 10,
+  17, 24, 32, 25, 18, 11,  4,  5,
+  12, 19, 26, 33, 40, 48, 41, 34,
+  27, 20, 13,  6,  7, 14, 21, 28,
+  35, 42, 49, 56, 57, 50, 43, 36,
+  29, 22, 15, 23, 30, 37, 44, 51,
+  58, 59, 52, 45, 38, 31, 39, 46,
+  53, 60, 61, 54, 47, 55, 62, 63
+);
+
+// unpacks a row of a block and does the first iDCT pass on it in one go, so
+// each block's coefficients are walked once per row instead of once per pixel
+void main() {
+  int i;
+  int x[8], y[8];
+
+  // find our block and which of its rows we are
+  ivec2 i_tex_coords = ivec2(v_tex_coords * plane_dims);
+  ivec2 block = i_tex_coords >> 3;
+  int row = i_tex_coords.y % 8;
+  int table_row = quant_row(block.y);
+
+  for (i = 0; i < 8; i++) {
+    y[i] = 0;
+  }
+
+  // grab the index where our block's coefficients start
+  int index = int(texelFetch(index_texture, block, 0).r);
+
+  // keep just the coefficients which land in our row
+  int pos = -1;
+  while (pos < 63) {
+    uint packed_coeff = texelFetch(packed_coeffs, index).r;
+    index += 1;
+
+    if (packed_coeff == uint(0)) {
+      // all the rest of the coefficients are zero
+      break;
+    }
+
+    int zeros = int((packed_coeff >> 12) & uint(0x000f));
+    int coeff;
+    if ((packed_coeff & uint(0x0fff)) == uint(0x800)) {
+      // escaped coefficients are stored whole in the next word
+      uint escaped = texelFetch(packed_coeffs, index).r;
+      index += 1;
+      coeff = int(escaped);
+      if ((escaped & uint(0x8000)) == uint(0x8000)) {
+        coeff -= 0x10000;
+      }
+    } else if ((packed_coeff & uint(0x800)) == uint(0x800)) {
+      coeff = int(uint(~0x0fff) | (packed_coeff & uint(0x0fff)));
+    } else {
+      coeff = int(packed_coeff & uint(0x0fff));
+    }
+
+    pos += zeros + 1;
+    if (pos > 63) {
+      break;
+    }
+
+    int n = NATURAL[pos];
+    if ((n >> 3) == row && coeff != 0) {
+      // dequantize, saturating as the unpack shader does
+      int q = int(texelFetch(quant_table, ivec2(n & 7, table_row + row), 0).r);
+      y[n & 7] = clamp(coeff * q, -32768, 32767);
+    }
+  }
+
+  // we have to shift up for headroom in the transform
+  for (i = 0; i < 8; i++) {
+    y[i] = y[i] << headroom;
+  }
+
+  // transform
+  idct8(x, y);
+
+  // stuff the column into our output colors
+  pass1_top = ivec4(x[0], x[1], x[2], x[3]);
+  pass1_bot = ivec4(x[4], x[5], x[6], x[7]);
+}
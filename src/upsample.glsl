@@ -0,0 +1,58 @@
+#version 140
+
+// the upsampling shared by the shaders that turn planes into color. the decoder
+// puts this in front of each of them, and each one supplies its own fetch.
+
+// the upsampling modes
+const int NEAREST = 0;
+const int BILINEAR = 1;
+const int FANCY = 2;
+
+uniform ivec2 max_sampling;
+uniform int upsampling;
+
+// a sample of the plane at origin, clamping to its edges
+int fetch(ivec2 origin, ivec2 covered, ivec2 p);
+
+// the sample of a plane under the pixel at tex_coords, upsampled as the CPU
+// conversion does it
+float upsample(ivec2 tex_coords, ivec2 origin, ivec2 covered, ivec2 sampling) {
+  ivec2 nearest = (tex_coords * sampling) / max_sampling;
+
+  if (upsampling == BILINEAR) {
+    // samples are sited at the center of the pixels they cover
+    vec2 c = (vec2(tex_coords) + 0.5) * vec2(sampling) / vec2(max_sampling) - 0.5;
+    vec2 c0 = floor(c);
+    vec2 f = c - c0;
+    ivec2 p = ivec2(c0);
+    float top = mix(float(fetch(origin, covered, p)),
+                    float(fetch(origin, covered, p + ivec2(1, 0))), f.x);
+    float bot = mix(float(fetch(origin, covered, p + ivec2(0, 1))),
+                    float(fetch(origin, covered, p + ivec2(1, 1))), f.x);
+    return mix(top, bot, f.y);
+  }
+
+  if (upsampling == FANCY) {
+    // the neighbor on the same side of the nearest sample as we are gets a
+    // weight of 1 to the nearest sample's 3
+    bvec2 halved = equal(max_sampling, sampling * 2);
+    ivec2 odd = tex_coords & 1;
+    ivec2 p = tex_coords >> 1;
+    ivec2 n = p + odd * 2 - 1;
+    if (halved.x && halved.y) {
+      int this_sum = 3 * fetch(origin, covered, p) + fetch(origin, covered, ivec2(p.x, n.y));
+      int other_sum = 3 * fetch(origin, covered, ivec2(n.x, p.y)) + fetch(origin, covered, n);
+      return float((3 * this_sum + other_sum + 8 - odd.x) >> 4);
+    }
+    if (halved.x) {
+      return float((3 * fetch(origin, covered, ivec2(p.x, nearest.y)) +
+                    fetch(origin, covered, ivec2(n.x, nearest.y)) + 1 + odd.x) >> 2);
+    }
+    if (halved.y) {
+      return float((3 * fetch(origin, covered, ivec2(nearest.x, p.y)) +
+                    fetch(origin, covered, ivec2(nearest.x, n.y)) + 1 + odd.y) >> 2);
+    }
+  }
+
+  return float(fetch(origin, covered, nearest));
+}
@@ -0,0 +1,73 @@
+extern crate gpeg;
+
+use gpeg::atlas::{Atlas, Region};
+use gpeg::container::ColorModel;
+use gpeg::{jpeg, unpack_coeffs, Plane, PACK_ALIGNMENT};
+
+// a plane whose coefficients are all distinct so misplaced blocks show up
+fn make_plane(width: u32, height: u32, h: u8, v: u8, seed: i16) -> (Vec<i16>, Plane) {
+    let data: Vec<i16> = (0..width * height)
+        .map(|i| if i % 3 == 0 { (i as i16).wrapping_mul(seed) } else { 0 })
+        .collect();
+    let quant_table: Vec<u16> = (0..64).map(|i| i + seed as u16).collect();
+    let plane = Plane::new(width, height, h, v, &data, &quant_table);
+    (data, plane)
+}
+
+#[test]
+fn planes_are_stacked_in_order() {
+    let (y, y_plane) = make_plane(32, 16, 2, 2, 3);
+    let (cb, cb_plane) = make_plane(16, 8, 1, 1, 5);
+    let (cr, cr_plane) = make_plane(16, 8, 1, 1, 7);
+    let atlas = Atlas::new(&[y_plane, cb_plane, cr_plane]);
+
+    assert_eq!((atlas.width, atlas.height), (32, 32));
+    assert_eq!(atlas.regions[1], Region { x: 0, y: 16, width: 16, height: 8, h: 1, v: 1 });
+    assert_eq!(atlas.regions[2].y, 24);
+    assert_eq!(atlas.region_rows(), [0, 2, 3, i32::max_value()]);
+    assert_eq!(atlas.packed_coeffs.len() % PACK_ALIGNMENT, 0);
+    assert_eq!(atlas.quant_tables.len(), 3 * 64);
+    assert_eq!(atlas.quant_tables[64], 5);
+
+    // the whole atlas unpacks with each plane in its region and nothing in
+    // the unused blocks
+    let unpacked = unpack_coeffs(atlas.width, atlas.height, &atlas.packed_coeffs,
+                                 &atlas.packed_indices);
+    for (region, data) in atlas.regions.iter().zip([y, cb, cr].iter()) {
+        for row in 0..region.height {
+            let start = ((region.y + row) * atlas.width) as usize;
+            let plane_start = (row * region.width) as usize;
+            let plane_row = &data[plane_start..plane_start + region.width as usize];
+            assert!(&unpacked[start..start + region.width as usize] == plane_row);
+            assert!(unpacked[start + region.width as usize..start + atlas.width as usize]
+                    .iter().all(|&c| c == 0));
+        }
+    }
+}
+
+#[test]
+fn single_plane_is_unchanged() {
+    let (_, plane) = make_plane(24, 16, 1, 1, 11);
+    let packed_coeffs = plane.packed_coeffs.clone();
+    let packed_indices = plane.packed_indices.clone();
+    let atlas = Atlas::new(&[plane]);
+
+    assert_eq!((atlas.width, atlas.height), (24, 16));
+    assert!(atlas.packed_coeffs == packed_coeffs);
+    assert!(atlas.packed_indices == packed_indices);
+}
+
+#[test]
+fn from_jpeg_coefficients() {
+    let data = include_bytes!("data/baseline-420.jpg");
+    let coeffs = jpeg::decode(&mut data.iter().map(|&b| Ok(b))).unwrap();
+    let atlas = Atlas::from_coefficients(&coeffs).unwrap();
+    assert_eq!(atlas.color_model, ColorModel::YCbCr);
+    assert_eq!(atlas.precision, 8);
+    assert_eq!((atlas.width, atlas.height), (48, 64));
+    let sizes: Vec<_> = atlas.regions.iter().map(|r| (r.y, r.width, r.height, r.h, r.v)).collect();
+    assert_eq!(sizes, vec![(0, 48, 32, 2, 2), (32, 24, 16, 1, 1), (48, 24, 16, 1, 1)]);
+    for (table, plane) in atlas.quant_tables.chunks(64).zip(coeffs.planes.iter()) {
+        assert_eq!(table, &plane.quant_table[..]);
+    }
+}
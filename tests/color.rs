@@ -0,0 +1,269 @@
+extern crate gpeg;
+
+use gpeg::atlas::Atlas;
+use gpeg::color::{self, ColorMatrix, ColorRange, ColorSpace, Upsampling};
+use gpeg::container::ColorModel;
+use gpeg::Plane;
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+}
+
+#[test]
+fn full_range_bt601_is_jfif() {
+    let matrix = ColorMatrix::new(ColorSpace::Bt601, ColorRange::Full);
+    assert_eq!(matrix.columns[0], [1.0; 3]);
+    assert!(close(matrix.columns[2][0], 1.402));
+    assert!(close(matrix.columns[1][1], -0.34414));
+    assert!(close(matrix.columns[2][1], -0.71414));
+    assert!(close(matrix.columns[1][2], 1.772));
+    assert_eq!(matrix.offset, [128.0; 3]);
+
+    assert_eq!(matrix.apply(-128.0, 0.0, 0.0), (0, 0, 0));
+    assert_eq!(matrix.apply(127.0, 0.0, 0.0), (255, 255, 255));
+    // saturated colors clamp rather than wrap
+    assert_eq!(matrix.apply(0.0, -128.0, 127.0), (255, 81, 0));
+}
+
+#[test]
+fn bt709_coefficients() {
+    let matrix = ColorMatrix::new(ColorSpace::Bt709, ColorRange::Full);
+    assert!(close(matrix.columns[2][0], 1.5748));
+    assert!(close(matrix.columns[1][1], -0.18733));
+    assert!(close(matrix.columns[2][1], -0.46812));
+    assert!(close(matrix.columns[1][2], 1.8556));
+}
+
+#[test]
+fn limited_range_stretches_levels() {
+    for &space in [ColorSpace::Bt601, ColorSpace::Bt709].iter() {
+        let matrix = ColorMatrix::new(space, ColorRange::Limited);
+        // black is 16 and white is 235, with the samples level shifted by 128
+        assert_eq!(matrix.apply(16.0 - 128.0, 0.0, 0.0), (0, 0, 0));
+        assert_eq!(matrix.apply(235.0 - 128.0, 0.0, 0.0), (255, 255, 255));
+        assert_eq!(matrix.apply(0.0, 0.0, 0.0), (130, 130, 130));
+        // and anything outside that saturates
+        assert_eq!(matrix.apply(-128.0, 0.0, 0.0), (0, 0, 0));
+        assert_eq!(matrix.apply(127.0, 0.0, 0.0), (255, 255, 255));
+    }
+}
+
+#[test]
+fn convert_subsampled_atlas() {
+    // a 16x16 luma plane and 8x8 chroma planes, as 4:2:0 has
+    let zeros = vec![0; 16 * 16];
+    let planes = vec![
+        Plane::new(16, 16, 2, 2, &zeros, &[1; 64]),
+        Plane::new(8, 8, 1, 1, &zeros[..64], &[1; 64]),
+        Plane::new(8, 8, 1, 1, &zeros[..64], &[1; 64]),
+    ];
+    let atlas = Atlas::new(&planes);
+
+    // luma counts up across each row and chroma is strongly blue on its right
+    // half
+    let mut samples = vec![0; (atlas.width * atlas.height) as usize];
+    for y in 0..16 {
+        for x in 0..16 {
+            samples[y * 16 + x] = x as i16 * 8 - 64;
+        }
+    }
+    for y in 0..8 {
+        for x in 4..8 {
+            samples[(16 + y) * 16 + x] = 100;
+        }
+    }
+
+    let matrix = ColorMatrix::new(ColorSpace::Bt601, ColorRange::Full);
+    // the image is cropped to less than the planes cover
+    let pixels = color::convert(13, 11, &atlas, &samples, &matrix, Upsampling::Nearest);
+    assert_eq!(pixels.len(), 13 * 11 * 3);
+    for y in 0..11 {
+        for x in 0..13 {
+            let cb = if x >= 8 { 100.0 } else { 0.0 };
+            let expected = matrix.apply(x as f32 * 8.0 - 64.0, cb, 0.0);
+            let i = (y * 13 + x) * 3;
+            assert_eq!((pixels[i], pixels[i + 1], pixels[i + 2]), expected);
+        }
+    }
+}
+
+#[test]
+fn convert_gray_atlas() {
+    let atlas = Atlas::new(&[Plane::new(8, 8, 1, 1, &[0; 64], &[1; 64])]);
+    let samples: Vec<i16> = (0..64).map(|i| i * 4 - 128).collect();
+    let matrix = ColorMatrix::new(ColorSpace::Bt709, ColorRange::Limited);
+    let pixels = color::convert(8, 8, &atlas, &samples, &matrix, Upsampling::Bilinear);
+    for (i, &s) in samples.iter().enumerate() {
+        let (y, _, _) = matrix.apply(s as f32, 0.0, 0.0);
+        assert_eq!(&pixels[i * 3..i * 3 + 3], &[y, y, y]);
+    }
+}
+
+// a 4:2:0 atlas whose chroma planes hold the given samples, with flat luma
+fn chroma_atlas(cb: &[i16]) -> (Atlas, Vec<i16>) {
+    let zeros = vec![0; 16 * 16];
+    let planes = vec![
+        Plane::new(16, 16, 2, 2, &zeros, &[1; 64]),
+        Plane::new(8, 8, 1, 1, &zeros[..64], &[1; 64]),
+        Plane::new(8, 8, 1, 1, &zeros[..64], &[1; 64]),
+    ];
+    let atlas = Atlas::new(&planes);
+    let mut samples = vec![0; (atlas.width * atlas.height) as usize];
+    for y in 0..8 {
+        let start = ((16 + y) * atlas.width) as usize;
+        samples[start..start + 8].copy_from_slice(&cb[y as usize * 8..y as usize * 8 + 8]);
+    }
+    (atlas, samples)
+}
+
+// the blue channel of each pixel, which only Cb and the flat luma feed
+fn blue(pixels: &[u8]) -> Vec<u8> {
+    pixels.chunks(3).map(|p| p[2]).collect()
+}
+
+#[test]
+fn upsampling_flat_chroma_is_flat() {
+    let (atlas, samples) = chroma_atlas(&[20; 64]);
+    let matrix = ColorMatrix::new(ColorSpace::Bt601, ColorRange::Full);
+    let expected = matrix.apply(0.0, 20.0, 0.0);
+    for &mode in [Upsampling::Nearest, Upsampling::Bilinear, Upsampling::Fancy].iter() {
+        let pixels = color::convert(15, 13, &atlas, &samples, &matrix, mode);
+        for p in pixels.chunks(3) {
+            assert_eq!((p[0], p[1], p[2]), expected, "{:?}", mode);
+        }
+    }
+}
+
+#[test]
+fn upsampling_an_edge() {
+    // chroma steps from -40 to 40 halfway across
+    let cb: Vec<i16> = (0..64).map(|i| if i % 8 < 4 { -40 } else { 40 }).collect();
+    let (atlas, samples) = chroma_atlas(&cb);
+    let matrix = ColorMatrix::new(ColorSpace::Bt601, ColorRange::Full);
+    let b = |c: f32| matrix.apply(0.0, c, 0.0).2;
+    let row = |mode| {
+        let pixels = color::convert(16, 16, &atlas, &samples, &matrix, mode);
+        blue(&pixels)[16 * 5..16 * 6].to_vec()
+    };
+
+    let nearest = row(Upsampling::Nearest);
+    assert_eq!(&nearest[6..10], &[b(-40.0), b(-40.0), b(40.0), b(40.0)]);
+
+    // the two pixels either side of the edge are a quarter of the way across
+    // it, and the rest are untouched
+    let bilinear = row(Upsampling::Bilinear);
+    assert_eq!(&bilinear[5..11], &[b(-40.0), b(-40.0), b(-20.0), b(20.0), b(40.0), b(40.0)]);
+
+    // libjpeg rounds the odd and even pixels differently
+    let fancy = row(Upsampling::Fancy);
+    assert_eq!(&fancy[5..11], &[b(-40.0), b(-40.0), b(-20.0), b(20.0), b(40.0), b(40.0)]);
+    assert_eq!(fancy, bilinear);
+}
+
+#[test]
+fn fancy_matches_libjpeg_h2v2() {
+    let cb: Vec<i16> = (0..64).map(|i| ((i * 37) % 101) as i16 - 50).collect();
+    let (atlas, samples) = chroma_atlas(&cb);
+    // an identity-like matrix on blue so the samples come straight through
+    let matrix = ColorMatrix {
+        columns: [[0.0; 3], [0.0, 0.0, 1.0], [0.0; 3]],
+        offset: [0.0, 0.0, 128.0],
+    };
+    let pixels = blue(&color::convert(16, 16, &atlas, &samples, &matrix, Upsampling::Fancy));
+
+    // h2v2_fancy_upsample from libjpeg, clamping at the edges as it does
+    let s = |x: i32, y: i32| cb[(y.max(0).min(7) * 8 + x.max(0).min(7)) as usize] as i32;
+    for y in 0..16 {
+        let (row, near) = (y / 2, if y % 2 == 0 { y / 2 - 1 } else { y / 2 + 1 });
+        let colsum = |x: i32| 3 * s(x, row) + s(x, near);
+        for x in 0..16 {
+            let i = x / 2;
+            let value = if x % 2 == 0 {
+                (3 * colsum(i) + colsum(i - 1) + 8) >> 4
+            } else {
+                (3 * colsum(i) + colsum(i + 1) + 7) >> 4
+            };
+            assert_eq!(pixels[(y * 16 + x) as usize] as i32, value + 128, "at {}, {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn cmyk_conversions() {
+    let matrix = ColorMatrix::new(ColorSpace::Bt601, ColorRange::Full);
+    // the samples are level shifted, so -128 is 0 and 127 is 255
+    let white_ink = [-128.0, -128.0, -128.0, -128.0];
+    assert_eq!(matrix.apply_cmyk(ColorModel::Cmyk, white_ink), (255, 255, 255));
+    assert_eq!(matrix.apply_cmyk(ColorModel::InvertedCmyk, white_ink), (0, 0, 0));
+
+    // full cyan and half black
+    let cmyk = [127.0, -128.0, -128.0, -1.0];
+    assert_eq!(matrix.apply_cmyk(ColorModel::Cmyk, cmyk), (0, 128, 128));
+    let inverted = [-128.0, 127.0, 127.0, 0.0];
+    assert_eq!(matrix.apply_cmyk(ColorModel::InvertedCmyk, inverted), (0, 128, 128));
+
+    // YCCK carries the inks through a YCbCr transform, and inverts black
+    let ycck = [-128.0, 0.0, 0.0, 127.0];
+    assert_eq!(matrix.apply_cmyk(ColorModel::Ycck, ycck), (255, 255, 255));
+    let ycck = [127.0, 0.0, 0.0, 127.0];
+    assert_eq!(matrix.apply_cmyk(ColorModel::Ycck, ycck), (0, 0, 0));
+
+    // samples outside 8 bits are clamped first
+    let overshoot = [-300.0, -300.0, -300.0, 300.0];
+    assert_eq!(matrix.apply_cmyk(ColorModel::Cmyk, overshoot), (0, 0, 0));
+}
+
+#[test]
+fn convert_cmyk_atlas() {
+    let zeros = vec![0; 64];
+    let planes: Vec<_> = (0..4).map(|_| Plane::new(8, 8, 1, 1, &zeros, &[1; 64])).collect();
+    let atlas = Atlas::with_color_model(&planes, ColorModel::InvertedCmyk);
+    let samples: Vec<i16> = (0..4 * 64).map(|i| (i % 251) as i16 - 128).collect();
+    let matrix = ColorMatrix::new(ColorSpace::Bt601, ColorRange::Full);
+    let pixels = color::convert(8, 8, &atlas, &samples, &matrix, Upsampling::Nearest);
+    for i in 0..64 {
+        let s = |plane: usize| samples[plane * 64 + i] as f32;
+        let expected = matrix.apply_cmyk(ColorModel::InvertedCmyk, [s(0), s(1), s(2), s(3)]);
+        assert_eq!((pixels[i * 3], pixels[i * 3 + 1], pixels[i * 3 + 2]), expected);
+    }
+}
+
+#[test]
+fn twelve_bit_levels() {
+    let matrix = ColorMatrix::with_precision(ColorSpace::Bt601, ColorRange::Full, 12);
+    assert_eq!(color::max_level(12), 4095.0);
+    assert_eq!(matrix.offset, [2048.0; 3]);
+    let gray = |y: f32| matrix.apply_u16(ColorModel::Gray, [y, 0.0, 0.0, 0.0], 12);
+    assert_eq!(gray(-2048.0), (0, 0, 0));
+    assert_eq!(gray(2047.0), (4095, 4095, 4095));
+    // levels between the 8-bit ones survive
+    assert_eq!(gray(1.0), (2049, 2049, 2049));
+    // and color is 16 times the 8-bit result, less its rounding
+    let eight = ColorMatrix::new(ColorSpace::Bt601, ColorRange::Full);
+    let (r, g, b) = eight.apply(10.0, -20.0, 30.0);
+    let (r12, g12, b12) = matrix.apply_u16(ColorModel::YCbCr, [160.0, -320.0, 480.0, 0.0], 12);
+    for &(a, b) in [(r12, r), (g12, g), (b12, b)].iter() {
+        assert!((a as i32 - 16 * b as i32).abs() <= 8, "{} against {}", a, b);
+    }
+
+    let limited = ColorMatrix::with_precision(ColorSpace::Bt709, ColorRange::Limited, 12);
+    let black = (16 - 128) * 16;
+    assert_eq!(limited.apply_u16(ColorModel::Gray, [black as f32, 0.0, 0.0, 0.0], 12), (0, 0, 0));
+
+    // inverted CMYK with no ink at all is white
+    let no_ink = [2047.0; 4];
+    assert_eq!(matrix.apply_u16(ColorModel::InvertedCmyk, no_ink, 12), (4095, 4095, 4095));
+}
+
+#[test]
+fn convert_twelve_bit_atlas() {
+    let mut atlas = Atlas::new(&[Plane::new(8, 8, 1, 1, &[0; 64], &[1; 64])]);
+    atlas.precision = 12;
+    let samples: Vec<i16> = (0..64).map(|i| i * 64 - 2048).collect();
+    let matrix = ColorMatrix::with_precision(ColorSpace::Bt601, ColorRange::Full, 12);
+    let pixels = color::convert_u16(8, 8, &atlas, &samples, &matrix, Upsampling::Nearest);
+    for (i, &s) in samples.iter().enumerate() {
+        let level = (s + 2048) as u16;
+        assert_eq!(&pixels[i * 3..i * 3 + 3], &[level, level, level]);
+    }
+}
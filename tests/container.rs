@@ -0,0 +1,139 @@
+extern crate gpeg;
+
+use gpeg::container::{self, ColorModel, Image};
+use gpeg::markers::Error;
+use gpeg::{Plane, PACK_ALIGNMENT};
+
+const FILE_HEADER: usize = 24;
+const PLANE_HEADER: usize = 148;
+
+// a 4:2:0 image with a mix of small and escaped coefficients
+fn image() -> Image {
+    let plane = |width: u32, height: u32, h: u8, v: u8, q: u16| {
+        let data: Vec<i16> = (0..width * height)
+            .map(|i| if i % 7 == 0 { (i as i16).wrapping_mul(311) } else { 0 })
+            .collect();
+        let quant_table: Vec<u16> = (0..64).map(|i| q + i).collect();
+        Plane::new(width, height, h, v, &data, &quant_table)
+    };
+    Image {
+        width: 13,
+        height: 11,
+        color_model: ColorModel::YCbCr,
+        precision: 8,
+        planes: vec![plane(16, 16, 2, 2, 1), plane(8, 8, 1, 1, 2), plane(8, 8, 1, 1, 3)],
+    }
+}
+
+fn write(image: &Image) -> Vec<u8> {
+    let mut out = vec![];
+    container::write(&mut out, image).unwrap();
+    out
+}
+
+fn read(data: &[u8]) -> Result<Image, Error> {
+    container::read(&mut &data[..])
+}
+
+fn assert_malformed(data: &[u8]) {
+    match read(data) {
+        Err(Error::Malformed(_)) => {},
+        Err(err) => panic!("unexpected error {}", err),
+        Ok(_) => panic!("read a malformed container"),
+    }
+}
+
+// where the first plane's packed coefficients and block indices start
+fn first_plane_offsets(image: &Image) -> (usize, usize) {
+    let coeffs = FILE_HEADER + image.planes.len() * PLANE_HEADER;
+    (coeffs, coeffs + image.planes[0].packed_coeffs.len() * 2)
+}
+
+#[test]
+fn round_trip() {
+    let image = image();
+    let read = read(&write(&image)).unwrap();
+    assert_eq!((read.width, read.height), (13, 11));
+    assert_eq!(read.color_model, ColorModel::YCbCr);
+    assert_eq!(read.precision, 8);
+    assert_eq!(read.planes.len(), 3);
+    for (a, b) in read.planes.iter().zip(image.planes.iter()) {
+        assert_eq!((a.width, a.height, a.h, a.v), (b.width, b.height, b.h, b.v));
+        assert_eq!(a.packed_coeffs, b.packed_coeffs);
+        assert_eq!(a.packed_indices, b.packed_indices);
+        assert_eq!(a.quant_table, b.quant_table);
+    }
+}
+
+#[test]
+fn truncated() {
+    let image = image();
+    let data = write(&image);
+    let (coeffs, indices) = first_plane_offsets(&image);
+    // in the file header, a plane header, the packed coefficients, the block
+    // indices and at the very end
+    for &len in [10, FILE_HEADER + 100, coeffs + 6, indices + 5, data.len() - 1].iter() {
+        assert_malformed(&data[..len]);
+    }
+}
+
+#[test]
+fn huge_counts() {
+    // plane headers claiming far more coefficients and block indices than
+    // could ever be allocated, followed by only a few of them
+    let image = image();
+    let (coeffs, indices) = first_plane_offsets(&image);
+    let mut data = write(&image)[..coeffs + 10].to_vec();
+    let header = FILE_HEADER;
+    data[header + 12..header + 16].copy_from_slice(&[0, 0, 0, 0xf0]);
+    assert_malformed(&data);
+
+    // 0x10000 by 0xffff blocks
+    let mut data = write(&image)[..indices + 10].to_vec();
+    data[header..header + 4].copy_from_slice(&[0, 0, 8, 0]);
+    data[header + 4..header + 8].copy_from_slice(&[0xf8, 0xff, 7, 0]);
+    data[header + 16..header + 20].copy_from_slice(&[0, 0, 0xff, 0xff]);
+    assert_malformed(&data);
+}
+
+#[test]
+fn index_out_of_range() {
+    let image = image();
+    let (_, indices) = first_plane_offsets(&image);
+    let mut data = write(&image);
+    data[indices + 4..indices + 8].copy_from_slice(&[0x00, 0xff, 0xff, 0xff]);
+    assert_malformed(&data);
+
+    // an index inside the stream that isn't where the block before ended
+    let mut data = write(&image);
+    data[indices + 4] += 1;
+    assert_malformed(&data);
+}
+
+#[test]
+fn extra_packed_coefficients() {
+    // the header claims another aligned chunk of coefficients beyond the last
+    // block's padding, and the chunk is there
+    let image = image();
+    let (_, indices) = first_plane_offsets(&image);
+    let original = write(&image);
+    let num_coeffs = image.planes[0].packed_coeffs.len() + PACK_ALIGNMENT;
+    let mut data = original[..indices].to_vec();
+    data.extend(vec![0; PACK_ALIGNMENT * 2]);
+    data.extend_from_slice(&original[indices..]);
+    let field = FILE_HEADER + 12;
+    data[field] = num_coeffs as u8;
+    data[field + 1] = (num_coeffs >> 8) as u8;
+    assert_malformed(&data);
+}
+
+#[test]
+fn unknown_version() {
+    let mut data = write(&image());
+    assert_eq!(&data[4..6], &[1, 0]);
+    data[4] = 2;
+    match read(&data) {
+        Err(Error::Unsupported(_)) => {},
+        _ => panic!("read a container of an unknown version"),
+    }
+}
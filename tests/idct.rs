@@ -0,0 +1,131 @@
+extern crate gpeg;
+
+use gpeg::idct::{headroom, idct_plane};
+use std::f64::consts::PI;
+
+// a single block with only a DC coefficient, which decodes to a flat block
+fn dc_block(dc: i16) -> Vec<i16> {
+    let mut data = vec![0; 64];
+    data[0] = dc;
+    data
+}
+
+// the weight of frequency u at sample k in the exact DCT
+fn basis(k: usize, u: usize) -> f64 {
+    let c = if u == 0 { 0.5f64.sqrt() } else { 1.0 };
+    c * ((2 * k + 1) as f64 * u as f64 * PI / 16.0).cos()
+}
+
+// the exact transform of a block, rounded to whole samples
+fn exact_idct(data: &[i16]) -> Vec<i16> {
+    let mut out = vec![0; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            let mut sum = 0.0;
+            for v in 0..8 {
+                for u in 0..8 {
+                    sum += data[v * 8 + u] as f64 * basis(x, u) * basis(y, v);
+                }
+            }
+            out[y * 8 + x] = (sum / 4.0).round() as i16;
+        }
+    }
+    out
+}
+
+// checks a block's samples are as close to the exact transform as headroom
+// allows. 8-bit samples are within the rounding of the integer transform, but
+// 12-bit ones get no headroom and lose the fractional bits between passes,
+// which can put them up to 5 levels out.
+fn assert_near_exact(data: &[i16], precision: u8) {
+    let max_error = if precision == 12 { 5 } else { 1 };
+    let exact = exact_idct(data);
+    for (&a, &b) in idct_plane(8, 8, data, precision).iter().zip(exact.iter()) {
+        assert!((a as i32 - b as i32).abs() <= max_error, "{} against {}", a, b);
+    }
+}
+
+#[test]
+fn headroom_fills_sixteen_bits() {
+    assert_eq!(headroom(8), 4);
+    assert_eq!(headroom(12), 0);
+}
+
+#[test]
+fn eight_bit_dc() {
+    // JPEG's DC is eight times the mean of the level shifted samples
+    assert!(idct_plane(8, 8, &dc_block(8 * 100), 8).iter().all(|&s| s == 100));
+    assert!(idct_plane(8, 8, &dc_block(-8 * 128), 8).iter().all(|&s| s == -128));
+}
+
+#[test]
+fn twelve_bit_dc_does_not_overflow() {
+    // the extremes of 12-bit samples, which would overflow with 8-bit headroom
+    for &dc in [8 * 2047, -8 * 2048, 8 * 1000].iter() {
+        assert_near_exact(&dc_block(dc), 12);
+    }
+}
+
+#[test]
+fn twelve_bit_ac_does_not_wrap() {
+    // the highest frequency at full and half strength, which only scale if
+    // nothing wrapped around in between
+    let block = |c: i16| {
+        let mut data = vec![0; 64];
+        data[63] = c;
+        data
+    };
+    assert!(idct_plane(8, 8, &block(16000), 12).iter().any(|&s| s.abs() > 2000));
+    assert_near_exact(&block(16000), 12);
+    assert_near_exact(&block(8000), 12);
+}
+
+#[test]
+fn twelve_bit_precision() {
+    // noise over the full range of 12-bit samples, and the same scaled down to
+    // 8 bits, through the forward transform rounded as an encoder would
+    let mut seed = 1u32;
+    let mut random = || {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        ((seed >> 16) & 0xfff) as i32 - 2048
+    };
+    for _ in 0..200 {
+        let samples: Vec<i32> = (0..64).map(|_| random()).collect();
+        for &(precision, scale) in [(8, 16), (12, 1)].iter() {
+            let mut data = vec![0; 64];
+            for v in 0..8 {
+                for u in 0..8 {
+                    let mut sum = 0.0;
+                    for k in 0..64 {
+                        sum += (samples[k] / scale) as f64 * basis(k & 7, u) * basis(k >> 3, v);
+                    }
+                    data[v * 8 + u] = (sum / 4.0).round() as i16;
+                }
+            }
+            assert_near_exact(&data, precision);
+        }
+    }
+}
+
+// blocks of dequantized coefficients from random and high contrast samples,
+// each followed by what the reference transform in tmp/dct.c gives for it when
+// shifted up by four bits of headroom the way the shaders do. the blocks are
+// laid out side by side in a plane so the stride is exercised too.
+#[test]
+fn matches_reference() {
+    let values: Vec<i16> = include_str!("data/idct-reference.txt").split_whitespace()
+        .map(|s| s.parse().unwrap()).collect();
+    let blocks = values.len() / 128;
+    assert_eq!(blocks, 64);
+    let width = 8 * blocks;
+    let mut coeffs = vec![0; 64 * blocks];
+    let mut expected = vec![0; 64 * blocks];
+    for b in 0..blocks {
+        for k in 0..64 {
+            let idx = (k >> 3) * width + (b << 3) + (k & 7);
+            coeffs[idx] = values[b * 128 + k];
+            expected[idx] = values[b * 128 + 64 + k];
+        }
+    }
+    assert_eq!(idct_plane(width as u32, 8, &coeffs, 8), expected);
+}
@@ -0,0 +1,234 @@
+extern crate gpeg;
+
+use gpeg::jpeg;
+use gpeg::markers::Error;
+
+fn decode_bytes(data: &[u8]) -> jpeg::Coefficients {
+    jpeg::decode(&mut data.iter().map(|&b| Ok(b))).unwrap()
+}
+
+// compares decoded planes with what libjpeg decodes the same file to. the dump
+// has each component's quantization table in natural order followed by its
+// coefficients, laid out as in the plane.
+fn assert_matches_libjpeg(coeffs: &jpeg::Coefficients, expected: &str) {
+    let mut values = expected.split_whitespace().map(|s| s.parse::<i32>().unwrap());
+    for plane in coeffs.planes.iter() {
+        let table: Vec<u16> = values.by_ref().take(64).map(|v| v as u16).collect();
+        assert_eq!(plane.quant_table, table);
+        let data: Vec<i16> = values.by_ref().take(plane.data.len()).map(|v| v as i16).collect();
+        assert_eq!(plane.data, data);
+    }
+    assert_eq!(values.next(), None);
+}
+
+fn plane_layout(coeffs: &jpeg::Coefficients) -> Vec<(u8, u8, u32, u32)> {
+    coeffs.planes.iter().map(|p| (p.h, p.v, p.width, p.height)).collect()
+}
+
+// removes every segment with the given marker from before the first scan
+fn without_segments(data: &[u8], code: u8) -> Vec<u8> {
+    let mut out = data[..2].to_vec();
+    let mut i = 2;
+    while data[i + 1] != 0xda {
+        let len = ((data[i + 2] as usize) << 8) | data[i + 3] as usize;
+        if data[i + 1] != code {
+            out.extend_from_slice(&data[i..i + 2 + len]);
+        }
+        i += 2 + len;
+    }
+    out.extend_from_slice(&data[i..]);
+    out
+}
+
+// the fixtures are written by libjpeg at sizes that leave partial blocks and
+// MCUs at the right and bottom edges
+#[test]
+fn baseline_420() {
+    let coeffs = decode_bytes(include_bytes!("data/baseline-420.jpg"));
+    assert_eq!((coeffs.width, coeffs.height), (37, 21));
+    assert_eq!(plane_layout(&coeffs), vec![(2, 2, 48, 32), (1, 1, 24, 16), (1, 1, 24, 16)]);
+    assert_matches_libjpeg(&coeffs, include_str!("data/baseline-420.txt"));
+}
+
+#[test]
+fn baseline_444() {
+    let coeffs = decode_bytes(include_bytes!("data/baseline-444.jpg"));
+    assert_eq!((coeffs.width, coeffs.height), (24, 16));
+    assert_eq!(plane_layout(&coeffs), vec![(1, 1, 24, 16); 3]);
+    assert_matches_libjpeg(&coeffs, include_str!("data/baseline-444.txt"));
+}
+
+#[test]
+fn baseline_gray() {
+    let coeffs = decode_bytes(include_bytes!("data/baseline-gray.jpg"));
+    assert_eq!((coeffs.width, coeffs.height), (21, 13));
+    assert_eq!(plane_layout(&coeffs), vec![(1, 1, 24, 16)]);
+    assert_matches_libjpeg(&coeffs, include_str!("data/baseline-gray.txt"));
+}
+
+// each component takes its quantization table when its first scan starts, so
+// a table redefined between scans only applies to the components after it
+#[test]
+fn quant_table_redefined_between_scans() {
+    let data = include_bytes!("data/scans-444.jpg");
+    let original = decode_bytes(data);
+    let second_scan = (3..data.len()).filter(|&i| data[i - 1] == 0xff && data[i] == 0xda)
+                                     .nth(1).unwrap() - 1;
+    let mut redefined = data[..second_scan].to_vec();
+    redefined.extend_from_slice(&[0xff, 0xdb, 0x00, 0x43, 0x00]);
+    redefined.extend_from_slice(&[2; 64]);
+    redefined.extend_from_slice(&data[second_scan..]);
+    let coeffs = decode_bytes(&redefined);
+
+    assert_eq!(coeffs.planes.len(), 3);
+    assert!(original.planes.iter().all(|p| p.quant_table == original.planes[0].quant_table));
+    assert!(original.planes[0].quant_table != vec![2; 64]);
+    assert_eq!(coeffs.planes[0].quant_table, original.planes[0].quant_table);
+    for (plane, original) in coeffs.planes.iter().zip(original.planes.iter()) {
+        if plane.id != 1 {
+            assert_eq!(plane.quant_table, vec![2; 64]);
+        }
+        assert_eq!(plane.data, original.data);
+    }
+}
+
+// libjpeg's simple progression codes the image as interleaved DC scans then
+// spectral selection bands with successive approximation, and the smooth image
+// leaves runs of empty blocks in the AC bands
+#[test]
+fn progressive_matches_baseline() {
+    let data = include_bytes!("data/smooth-420-progressive.jpg");
+    assert!(data.windows(2).any(|m| m == [0xff, 0xc2]));
+    let progressive = decode_bytes(data);
+    let baseline = decode_bytes(include_bytes!("data/smooth-420.jpg"));
+    assert_eq!((progressive.width, progressive.height), (64, 40));
+    assert_eq!(plane_layout(&progressive), plane_layout(&baseline));
+    for (p, b) in progressive.planes.iter().zip(baseline.planes.iter()) {
+        assert_eq!(p.quant_table, b.quant_table);
+        assert_eq!(p.data, b.data);
+    }
+}
+
+// checks that each restart position points just past the restart marker
+// ending the interval before it, or past the scan header for the first one
+fn assert_restarts_follow_markers(coeffs: &jpeg::Coefficients, data: &[u8]) {
+    let sos = (1..data.len()).find(|&i| data[i - 1] == 0xff && data[i] == 0xda).unwrap() - 1;
+    let sos_len = ((data[sos + 2] as usize) << 8) | data[sos + 3] as usize;
+    assert_eq!(coeffs.restarts[0].offset, sos + 2 + sos_len);
+    for (i, restart) in coeffs.restarts.iter().enumerate().skip(1) {
+        assert_eq!(restart.mcu, i as u32 * coeffs.restart_interval as u32);
+        assert_eq!(&data[restart.offset - 2..restart.offset], &[0xff, 0xd0 + (i as u8 - 1) % 8]);
+    }
+}
+
+// the 4:2:0 fixture again with a restart interval of one MCU
+#[test]
+fn restart_intervals() {
+    let data = include_bytes!("data/restart-420.jpg");
+    let coeffs = decode_bytes(data);
+    assert_eq!(coeffs.restart_interval, 1);
+    assert_eq!(coeffs.restarts.len(), 6);
+    assert!(coeffs.restarts.iter().all(|r| r.scan == 0));
+    assert_restarts_follow_markers(&coeffs, data);
+    assert_matches_libjpeg(&coeffs, include_str!("data/baseline-420.txt"));
+
+    // bytes that don't belong before a restart marker are skipped over
+    let at = coeffs.restarts[3].offset - 2;
+    let mut padded = data[..at].to_vec();
+    padded.extend_from_slice(&[0x12, 0x34, 0xff, 0x00]);
+    padded.extend_from_slice(&data[at..]);
+    let coeffs = decode_bytes(&padded);
+    assert_eq!(coeffs.restarts.len(), 6);
+    assert_restarts_follow_markers(&coeffs, &padded);
+    assert_matches_libjpeg(&coeffs, include_str!("data/baseline-420.txt"));
+}
+
+#[test]
+fn truncated() {
+    let data = include_bytes!("data/baseline-420.jpg");
+    // in the middle of the headers and then of the entropy coded data
+    for &len in [100, data.len() / 2].iter() {
+        match jpeg::decode(&mut data[..len].iter().map(|&b| Ok(b))) {
+            Err(Error::Malformed(_)) | Err(Error::Io(_)) => {},
+            _ => panic!("decoded {} bytes of a truncated file", len),
+        }
+    }
+}
+
+#[test]
+fn missing_tables() {
+    let data = include_bytes!("data/baseline-420.jpg");
+    // DHT and then DQT
+    for &code in [0xc4, 0xdb].iter() {
+        let data = without_segments(data, code);
+        match jpeg::decode(&mut data.iter().map(|&b| Ok(b))) {
+            Err(Error::Malformed(_)) => {},
+            _ => panic!("decoded without the {:x} segments", code),
+        }
+    }
+}
+
+// an 8x8 gray 12-bit image whose only coefficient is a DC of 20000, which
+// needs magnitude category 15 and a 16-bit quantization table
+fn twelve_bit_jpeg(sof: u8) -> Vec<u8> {
+    let mut data = vec![0xff, 0xd8];
+    // one table with pq=1, starting 300, 2 in zigzag order
+    data.extend_from_slice(&[0xff, 0xdb, 0x00, 0x83, 0x10, 0x01, 0x2c, 0x00, 0x02]);
+    for _ in 0..62 {
+        data.extend_from_slice(&[0x00, 0x01]);
+    }
+    data.extend_from_slice(&[0xff, sof, 0x00, 0x0b, 12, 0x00, 0x08, 0x00, 0x08, 0x01,
+                             0x01, 0x11, 0x00]);
+    // DC and AC tables with a single one bit code each, for category 15 and
+    // end of block
+    data.extend_from_slice(&[0xff, 0xc4, 0x00, 0x26]);
+    for &(class, symbol) in [(0x00, 15), (0x10, 0x00)].iter() {
+        data.push(class);
+        data.push(1);
+        data.extend_from_slice(&[0; 15]);
+        data.push(symbol);
+    }
+    data.extend_from_slice(&[0xff, 0xda, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3f, 0x00]);
+    // the DC code, 20000 in 15 bits, end of block and then padding
+    data.extend_from_slice(&[0x4e, 0x20, 0x7f]);
+    data.extend_from_slice(&[0xff, 0xd9]);
+    data
+}
+
+#[test]
+fn extended_twelve_bit() {
+    let data = twelve_bit_jpeg(0xc1);
+    let coeffs = jpeg::decode(&mut data.iter().map(|&b| Ok(b))).unwrap();
+    assert_eq!(coeffs.precision, 12);
+    assert_eq!((coeffs.width, coeffs.height), (8, 8));
+    let plane = &coeffs.planes[0];
+    assert_eq!(plane.data[0], 20000);
+    assert!(plane.data[1..].iter().all(|&c| c == 0));
+    assert_eq!(&plane.quant_table[..3], &[300, 2, 1]);
+}
+
+#[test]
+fn baseline_is_only_eight_bit() {
+    let data = twelve_bit_jpeg(0xc0);
+    match jpeg::decode(&mut data.iter().map(|&b| Ok(b))) {
+        Err(Error::Malformed(_)) => {},
+        _ => panic!("a 12-bit baseline frame was accepted"),
+    }
+}
+
+// the same 16x16 gray image written by libjpeg with huffman coding, with
+// arithmetic coding, restarts every two MCUs and non-default conditioning, and
+// with progressive arithmetic coding
+#[test]
+fn arithmetic_matches_huffman() {
+    let huffman = decode_bytes(include_bytes!("data/gray16.jpg"));
+    for data in [&include_bytes!("data/gray16-arithmetic.jpg")[..],
+                 &include_bytes!("data/gray16-arithmetic-progressive.jpg")[..]].iter() {
+        let coeffs = decode_bytes(data);
+        assert_eq!((coeffs.width, coeffs.height), (16, 16));
+        assert_eq!(coeffs.planes.len(), 1);
+        assert_eq!(coeffs.planes[0].data, huffman.planes[0].data);
+        assert_eq!(coeffs.planes[0].quant_table, huffman.planes[0].quant_table);
+    }
+    assert_eq!(&huffman.planes[0].data[..4], &[-58, -12, 3, -1]);
+}
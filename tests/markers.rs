@@ -0,0 +1,125 @@
+extern crate gpeg;
+
+use gpeg::markers::{self, AdobeSegment, AppSegment, ArithmeticConditioning, Error, FrameType,
+                    SegmentType};
+
+fn app(n: u8, data: &[u8]) -> AppSegment {
+    AppSegment { n: n, data: data.to_vec() }
+}
+
+#[test]
+fn adobe_segment() {
+    let data = b"Adobe\x00\x64\x80\x00\x00\x00\x02";
+    assert_eq!(AdobeSegment::parse(&app(14, data)), Some(AdobeSegment {
+        version: 100,
+        flags0: 0x8000,
+        flags1: 0,
+        transform: 2,
+    }));
+
+    // other applications' APP14 segments and short ones are ignored
+    assert_eq!(AdobeSegment::parse(&app(14, b"Ducky\x00\x64\x80\x00\x00\x00\x02")), None);
+    assert_eq!(AdobeSegment::parse(&app(14, b"Adobe\x00\x64")), None);
+    assert_eq!(AdobeSegment::parse(&app(13, data)), None);
+}
+
+#[test]
+fn extended_frames_and_wide_tables() {
+    let mut len = vec![0x00, 0x0b].into_iter().map(Ok);
+    assert_eq!(markers::parse_header(&mut len, 0xff, 0xc1).unwrap(),
+               (SegmentType::StartOfFrame(FrameType::ExtendedDct), 9));
+
+    // an 8-bit table followed by a 16-bit one, each in zigzag order
+    let mut data = vec![0x00];
+    data.extend((0..64).map(|i| i as u8 + 1));
+    data.push(0x11);
+    for i in 0..64u16 {
+        data.push(0x01);
+        data.push(i as u8);
+    }
+    let len = data.len();
+    let tables = markers::read_quantization_table(&mut data.into_iter().map(Ok), len).unwrap();
+    assert_eq!(tables.len(), 2);
+    assert_eq!(tables[0].id, 0);
+    assert_eq!(&tables[0].values[..3], &[1, 2, 6]);
+    assert_eq!(tables[1].id, 1);
+    assert_eq!(&tables[1].values[..3], &[256, 257, 261]);
+    assert_eq!(tables[1].values[63], 256 + 63);
+
+    // a 16-bit table's length doesn't fit 8-bit values
+    let mut data = vec![0x10];
+    data.extend(vec![0; 128]);
+    assert!(markers::read_quantization_table(&mut data.into_iter().map(Ok), 65).is_err());
+}
+
+// segments too short for what they claim to hold are rejected before anything
+// past their end is read, leaving the next marker in the input
+#[test]
+fn short_segments() {
+    let eoi = [0xff, 0xd9];
+
+    // a table needs 65 bytes but the segment only has 40
+    let mut data = vec![0x00];
+    data.extend(vec![1; 39]);
+    data.extend_from_slice(&eoi);
+    let mut input = data.into_iter().map(Ok);
+    match markers::read_quantization_table(&mut input, 40) {
+        Err(Error::Malformed(_)) => {},
+        _ => panic!("short quantization table accepted"),
+    }
+    assert!(input.map(|b| b.unwrap()).collect::<Vec<_>>().ends_with(&eoi));
+
+    // sixteen counts claiming two values, with only one in the segment
+    let mut data = vec![0x00, 0x02];
+    data.extend(vec![0; 15]);
+    data.push(0x05);
+    data.extend_from_slice(&eoi);
+    let mut input = data.into_iter().map(Ok);
+    match markers::read_huffman_table(&mut input, 18) {
+        Err(Error::Malformed(_)) => {},
+        _ => panic!("short huffman table accepted"),
+    }
+    assert_eq!(input.map(|b| b.unwrap()).collect::<Vec<_>>(), vec![0x05, 0xff, 0xd9]);
+
+    // two scan components need 8 bytes but the segment only has 6
+    let data = vec![0x02, 0x01, 0x00, 0x00, 0x3f, 0x00, 0xff, 0xd9];
+    let mut input = data.into_iter().map(Ok);
+    match markers::read_scan_header(&mut input, 6) {
+        Err(Error::Malformed(_)) => {},
+        _ => panic!("short scan header accepted"),
+    }
+    assert_eq!(input.count(), 7);
+
+    // three frame components need 15 bytes but the segment only has 9
+    let data = vec![0x08, 0x00, 0x10, 0x00, 0x10, 0x03, 0x01, 0x11, 0x00, 0xff, 0xd9];
+    let mut input = data.into_iter().map(Ok);
+    match markers::read_frame_header(&mut input, FrameType::BaselineDct, 9) {
+        Err(Error::Malformed(_)) => {},
+        _ => panic!("short frame header accepted"),
+    }
+    assert_eq!(input.count(), 5);
+}
+
+#[test]
+fn arithmetic_conditioning() {
+    let mut len = vec![0x00, 0x0b].into_iter().map(Ok);
+    assert_eq!(markers::parse_header(&mut len, 0xff, 0xca).unwrap(),
+               (SegmentType::StartOfFrame(FrameType::ProgressiveArithmeticDct), 9));
+    let mut len = vec![0x00, 0x06].into_iter().map(Ok);
+    assert_eq!(markers::parse_header(&mut len, 0xff, 0xcc).unwrap(),
+               (SegmentType::DefineArithmeticConditioning, 4));
+
+    // DC table 1 with L=2 and U=5, then AC table 0 with Kx=12
+    let data = vec![0x01, 0x52, 0x10, 0x0c];
+    let tables = markers::read_arithmetic_conditioning(&mut data.into_iter().map(Ok), 4).unwrap();
+    assert_eq!(tables, vec![
+        ArithmeticConditioning { class: 0, id: 1, value: 0x52 },
+        ArithmeticConditioning { class: 1, id: 0, value: 12 },
+    ]);
+
+    // L above U, and a Kx of zero
+    for data in [[0x00, 0x25], [0x10, 0x00]].iter() {
+        let mut input = data.iter().map(|&b| Ok(b));
+        assert!(markers::read_arithmetic_conditioning(&mut input, 2).is_err());
+    }
+}
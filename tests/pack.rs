@@ -0,0 +1,168 @@
+extern crate gpeg;
+
+use gpeg::{make_zigzag_table, pack_coeffs, packed_block_end, unpack_block, unpack_coeffs, ESCAPE,
+           PACK_ALIGNMENT};
+
+// a small xorshift generator so the tests are repeatable without pulling in
+// a dependency
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+
+    // mostly small values like real coefficients, with a good share of the
+    // values around the edges of the 12-bit field and of i16
+    fn coeff(&mut self) -> i16 {
+        static EXTREMES: [i16; 10] = [
+            -32768, 32767, -2049, -2048, -2047, 2047, 2048, 2049, -1, 1,
+        ];
+        match self.below(8) {
+            0 => EXTREMES[self.below(EXTREMES.len() as u32) as usize],
+            1 => self.next() as i16,
+            _ => self.below(255) as i16 - 127,
+        }
+    }
+}
+
+// fills the block at (bx, by) with roughly density/64 nonzero coefficients
+fn fill_block(rng: &mut Rng, data: &mut [i16], width: u32, bx: u32, by: u32, density: u32) {
+    let offset = ((by << 3) * width + (bx << 3)) as usize;
+    for j in 0..8 {
+        for i in 0..8 {
+            let c = if rng.below(64) < density { rng.coeff() } else { 0 };
+            data[offset + j * width as usize + i] = c;
+        }
+    }
+}
+
+// sets the coefficient at zigzag position k in the block at (bx, by)
+fn set_zigzag(data: &mut [i16], width: u32, bx: u32, by: u32, k: usize, value: i16) {
+    let zigzag = make_zigzag_table(width);
+    let offset = ((by << 3) * width + (bx << 3)) as usize;
+    data[offset + zigzag[k >> 3][k & 7]] = value;
+}
+
+fn check_roundtrip(width: u32, height: u32, data: &[i16]) {
+    let (packed, indices) = pack_coeffs(width, height, data);
+
+    assert_eq!(packed.len() % PACK_ALIGNMENT, 0);
+    assert_eq!(indices.len(), ((width >> 3) * (height >> 3)) as usize);
+
+    // each block must start exactly where the previous one ended
+    let mut index = 0;
+    for (b, &start) in indices.iter().enumerate() {
+        assert_eq!(start as usize, index, "index of block {} is wrong", b);
+        let (_, next) = unpack_block(&packed, index);
+        assert!(next > index, "block {} took no space", b);
+        assert_eq!(packed_block_end(&packed, index), Some(next));
+        index = next;
+    }
+    // and everything after the last block is padding
+    assert!(packed[index..].iter().all(|&p| p == 0));
+
+    let unpacked = unpack_coeffs(width, height, &packed, &indices);
+    assert!(unpacked == data, "unpacked coefficients differ for {}x{}", width, height);
+}
+
+#[test]
+fn random_sparse_planes() {
+    let mut rng = Rng(0x2545f491);
+    for _ in 0..200 {
+        let width = (rng.below(8) + 1) * 8;
+        let height = (rng.below(8) + 1) * 8;
+        let mut data = vec![0; (width * height) as usize];
+        for by in 0..height >> 3 {
+            for bx in 0..width >> 3 {
+                let density = match rng.below(4) {
+                    0 => 0,
+                    1 => rng.below(4),
+                    2 => rng.below(16),
+                    _ => rng.below(65),
+                };
+                fill_block(&mut rng, &mut data, width, bx, by, density);
+            }
+        }
+        check_roundtrip(width, height, &data);
+    }
+}
+
+#[test]
+fn all_zero_planes() {
+    for &(width, height) in [(8, 8), (16, 8), (64, 24)].iter() {
+        let data = vec![0; (width * height) as usize];
+        check_roundtrip(width, height, &data);
+    }
+}
+
+#[test]
+fn dense_extreme_blocks() {
+    let values = [-32768, 32767, -2048, 2047, 2048, -2049];
+    for &value in values.iter() {
+        let data = vec![value; 16 * 16];
+        check_roundtrip(16, 16, &data);
+    }
+}
+
+#[test]
+fn long_zero_runs() {
+    // a lone coefficient at every zigzag position exercises every run length,
+    // including runs longer than 15 and the last coefficient with no end of
+    // block marker after it
+    for k in 0..64 {
+        for &value in [1, -1, 2047, -2048, 32767].iter() {
+            let mut data = vec![0; 16 * 8];
+            set_zigzag(&mut data, 16, 0, 0, k, value);
+            set_zigzag(&mut data, 16, 1, 0, 63 - k, value);
+            check_roundtrip(16, 8, &data);
+        }
+    }
+}
+
+#[test]
+fn runs_of_exactly_sixteen() {
+    // runs that are multiples of 16 need zero-valued run words
+    let mut data = vec![0; 8 * 8];
+    set_zigzag(&mut data, 8, 0, 0, 0, 5);
+    set_zigzag(&mut data, 8, 0, 0, 17, -5);
+    set_zigzag(&mut data, 8, 0, 0, 50, 7);
+    check_roundtrip(8, 8, &data);
+
+    let mut data = vec![0; 8 * 8];
+    set_zigzag(&mut data, 8, 0, 0, 16, 3);
+    set_zigzag(&mut data, 8, 0, 0, 48, -3);
+    check_roundtrip(8, 8, &data);
+}
+
+#[test]
+fn trailing_zeros() {
+    let mut rng = Rng(0xdeadbeef);
+    for last in 0..64 {
+        let mut data = vec![0; 8 * 8];
+        for k in 0..last + 1 {
+            set_zigzag(&mut data, 8, 0, 0, k, rng.coeff());
+        }
+        set_zigzag(&mut data, 8, 0, 0, last, 9);
+        check_roundtrip(8, 8, &data);
+    }
+}
+
+#[test]
+fn malformed_blocks() {
+    assert_eq!(packed_block_end(&[0x0001, 0x0000, 0x0005], 0), Some(2));
+    // running off the end, an escape without its value and a run of zeros
+    // past the last coefficient
+    assert_eq!(packed_block_end(&[0x0005, 0x1003], 0), None);
+    assert_eq!(packed_block_end(&[0x0001, ESCAPE], 0), None);
+    assert_eq!(packed_block_end(&[0x0001, 0xf000, 0xf000, 0xf000, 0xf000], 0), None);
+}
@@ -0,0 +1,138 @@
+extern crate gpeg;
+
+use gpeg::jpeg::CoeffPlane;
+use gpeg::markers::Error;
+use gpeg::{planefile, read_data};
+use std::env;
+use std::fs::File;
+use std::io::Write;
+
+fn plane(id: u8, h: u8, v: u8, width: u32, height: u32, quant_table: Vec<u16>) -> CoeffPlane {
+    CoeffPlane {
+        id: id,
+        h: h,
+        v: v,
+        width: width,
+        height: height,
+        data: (0..width * height).map(|i| (i as i16).wrapping_mul(37)).collect(),
+        quant_table: quant_table,
+    }
+}
+
+fn write(planes: &[CoeffPlane]) -> Vec<u8> {
+    let mut out = vec![];
+    for p in planes {
+        planefile::write_plane(&mut out, p).unwrap();
+    }
+    out
+}
+
+fn read(data: &[u8]) -> Result<Vec<CoeffPlane>, Error> {
+    planefile::read_planes(&mut &data[..])
+}
+
+fn assert_malformed(data: &[u8]) {
+    match read(data) {
+        Err(Error::Malformed(_)) => {},
+        Err(err) => panic!("unexpected error {}", err),
+        Ok(_) => panic!("read a malformed plane file"),
+    }
+}
+
+// a quantized luma plane and a dequantized chroma one, which is stored
+// without a table
+fn two_planes() -> Vec<CoeffPlane> {
+    vec![plane(1, 2, 2, 32, 16, (1..65).collect()),
+         plane(2, 1, 1, 16, 8, vec![1; 64])]
+}
+
+#[test]
+fn round_trip() {
+    let planes = two_planes();
+    let data = write(&planes);
+    assert_eq!(data.len(), 16 + 128 + 32 * 16 * 2 + 16 + 16 * 8 * 2);
+    let read = read(&data).unwrap();
+    assert_eq!(read.len(), 2);
+    for (a, b) in read.iter().zip(planes.iter()) {
+        assert_eq!((a.id, a.h, a.v, a.width, a.height), (b.id, b.h, b.v, b.width, b.height));
+        assert_eq!(a.data, b.data);
+        assert_eq!(a.quant_table, b.quant_table);
+    }
+}
+
+#[test]
+fn truncated() {
+    let data = write(&two_planes());
+    // in the first header, its quantization table, its coefficients and then
+    // the second header
+    for &len in [10, 16 + 64, 16 + 128 + 100, 16 + 128 + 32 * 16 * 2 + 8].iter() {
+        assert_malformed(&data[..len]);
+    }
+    // an odd byte at the end
+    assert_malformed(&data[..data.len() - 1]);
+    // and nothing at all
+    assert_malformed(&[]);
+}
+
+#[test]
+fn huge_dimensions() {
+    // a header claiming far more coefficients than could ever be allocated,
+    // followed by only a few of them
+    let mut data = write(&two_planes())[..16 + 128 + 10].to_vec();
+    data[4..8].copy_from_slice(&[0, 0, 0, 0x40]);
+    data[8..12].copy_from_slice(&[0, 0, 0, 0x40]);
+    assert_malformed(&data);
+}
+
+#[test]
+fn bad_headers() {
+    let data = write(&two_planes());
+    let mut bad_magic = data.clone();
+    bad_magic[0] = b'X';
+    assert_malformed(&bad_magic);
+
+    // a width that isn't whole blocks
+    let mut bad_width = data.clone();
+    bad_width[4] = 30;
+    assert_malformed(&bad_width);
+
+    let mut bad_sampling = data.clone();
+    bad_sampling[13] = 5;
+    assert_malformed(&bad_sampling);
+
+    let mut zero_quant = data.clone();
+    zero_quant[16] = 0;
+    assert_malformed(&zero_quant);
+
+    let mut unknown_flags = data.clone();
+    unknown_flags[15] |= 0x80;
+    match read(&unknown_flags) {
+        Err(Error::Unsupported(_)) => {},
+        _ => panic!("read a plane with unknown flags"),
+    }
+}
+
+#[test]
+fn text_dumps() {
+    let path = env::temp_dir().join("gpeg-text-dump-test.txt");
+    let path = path.to_str().unwrap();
+    let write_text = |text: &str| File::create(path).unwrap().write_all(text.as_bytes()).unwrap();
+
+    write_text("-198 -8 0\n4 32767\n-32768\n");
+    assert_eq!(read_data(path).unwrap(), vec![-198, -8, 0, 4, 32767, -32768]);
+
+    for bad in ["1 2 x", "1 40000", "1.5"].iter() {
+        write_text(bad);
+        match read_data(path) {
+            Err(Error::Malformed(_)) => {},
+            _ => panic!("read {:?} as coefficients", bad),
+        }
+    }
+
+    write_text("");
+    assert_eq!(read_data(path).unwrap(), vec![]);
+    match read_data(&format!("{}.missing", path)) {
+        Err(Error::Io(_)) => {},
+        _ => panic!("read a missing text dump"),
+    }
+}